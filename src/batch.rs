@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek},
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    panic_message,
+    parser::{PngParser, MAX_PIXELS},
+    Png,
+};
+
+/// Settings shared by every decode in a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOptions {
+    /// Most images decoded at once
+    pub workers: NonZeroUsize,
+    /// Images with more pixels than this fail without being decoded. See
+    /// [`PngParser::max_pixels`]
+    pub max_pixels: Option<u64>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+            max_pixels: Some(MAX_PIXELS),
+        }
+    }
+}
+
+/// Decodes every reader on a pool of worker threads, returning the results
+/// in the same order as the readers. A failure only affects its own result,
+/// even if the decoder panics.
+pub fn decode_batch<R>(readers: Vec<R>, options: &BatchOptions) -> Vec<io::Result<Png>>
+where
+    R: Read + Seek + Send,
+{
+    let count = readers.len();
+    let readers: Vec<Mutex<Option<R>>> = readers.into_iter().map(|r| Mutex::new(Some(r))).collect();
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..options.workers.get().min(count) {
+            let sender = sender.clone();
+            let (readers, next) = (&readers, &next);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(reader) = readers.get(i) else {
+                    break;
+                };
+                let reader = reader
+                    .lock()
+                    .expect("Only ever locked by one worker")
+                    .take()
+                    .expect("Each reader is only taken once");
+                let result = panic::catch_unwind(AssertUnwindSafe(|| decode(reader, options)))
+                    .unwrap_or_else(|payload| Err(io::Error::other(panic_message(payload))));
+                _ = sender.send((i, result));
+            });
+        }
+    });
+    drop(sender);
+
+    let mut results: Vec<Option<io::Result<Png>>> = (0..count).map(|_| None).collect();
+    for (i, result) in receiver {
+        results[i] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|r| r.expect("Every reader is decoded"))
+        .collect()
+}
+
+/// Opens and decodes every file on a pool of worker threads, returning the
+/// results in the same order as the paths.
+pub fn decode_paths<P>(paths: &[P], options: &BatchOptions) -> Vec<io::Result<Png>>
+where
+    P: AsRef<Path>,
+{
+    // Files are opened lazily, so only `workers` are open at a time
+    let readers = paths.iter().map(|p| LazyFile(p.as_ref(), None)).collect();
+    decode_batch(readers, options)
+}
+
+fn decode(reader: impl Read + Seek, options: &BatchOptions) -> io::Result<Png> {
+    PngParser::new(reader)?
+        .max_pixels(options.max_pixels)
+        .parse()
+}
+
+/// File that is only opened once it is first read from
+struct LazyFile<'a>(&'a Path, Option<BufReader<File>>);
+
+impl LazyFile<'_> {
+    fn file(&mut self) -> io::Result<&mut BufReader<File>> {
+        match self.1 {
+            Some(ref mut file) => Ok(file),
+            None => Ok(self.1.insert(BufReader::new(File::open(self.0)?))),
+        }
+    }
+}
+
+impl Read for LazyFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file()?.read(buf)
+    }
+}
+
+impl Seek for LazyFile<'_> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.file()?.seek(pos)
+    }
+
+    fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
+        self.file()?.seek_relative(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_util::{PALETTE_PNG, TINY_PNG};
+
+    const BAD_COMPRESSION_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x01, 0x00, 0x00, 0x36,
+        0xac, 0x93, 0x13, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x48, 0xaf, 0xa4, 0x71, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_decode_batch() {
+        let readers = vec![
+            Cursor::new(TINY_PNG),
+            Cursor::new(&TINY_PNG[..20]),
+            Cursor::new(TINY_PNG),
+        ];
+        let options = BatchOptions {
+            workers: NonZeroUsize::new(2).unwrap(),
+            ..Default::default()
+        };
+        let results = decode_batch(readers, &options);
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].as_ref().is_ok_and(|p| p.width() == 1));
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_between_valid() {
+        let readers = vec![
+            Cursor::new(TINY_PNG),
+            Cursor::new(PALETTE_PNG),
+            Cursor::new(BAD_COMPRESSION_PNG),
+            Cursor::new(TINY_PNG),
+        ];
+        let results = decode_batch(readers, &BatchOptions::default());
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_err());
+        assert!(results[3].is_ok());
+    }
+
+    /// Reader over a png, or one that panics when read from, standing in for
+    /// a decoder bug
+    enum Source {
+        Png(Cursor<&'static [u8]>),
+        Panicking,
+    }
+
+    impl Read for Source {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self {
+                Self::Png(png) => png.read(buf),
+                Self::Panicking => panic!("Bad reader"),
+            }
+        }
+    }
+
+    impl Seek for Source {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            match self {
+                Self::Png(png) => png.seek(pos),
+                Self::Panicking => panic!("Bad reader"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_panic_between_valid() {
+        let readers = vec![
+            Source::Png(Cursor::new(TINY_PNG)),
+            Source::Panicking,
+            Source::Png(Cursor::new(TINY_PNG)),
+        ];
+        let results = decode_batch(readers, &BatchOptions::default());
+
+        assert!(results[0].is_ok());
+        let e = results[1].as_ref().unwrap_err();
+        assert_eq!(e.to_string(), "Decoder panicked: Bad reader");
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn test_max_pixels() {
+        assert_eq!(BatchOptions::default().max_pixels, Some(MAX_PIXELS));
+
+        let options = BatchOptions {
+            max_pixels: Some(0),
+            ..Default::default()
+        };
+        let results = decode_batch(vec![Cursor::new(TINY_PNG)], &options);
+        assert!(results[0].is_err());
+    }
+
+    #[test]
+    fn test_decode_paths() {
+        let path = std::env::temp_dir().join("png-test-decode-paths.png");
+        std::fs::write(&path, TINY_PNG).unwrap();
+        let missing = std::env::temp_dir().join("png-test-decode-paths-missing.png");
+
+        let results = decode_paths(&[&path, &missing], &BatchOptions::default());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+}
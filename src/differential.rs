@@ -0,0 +1,203 @@
+//! Decodes the same bytes with this crate and with the reference `png` crate
+//! and reports where they disagree. Meant for running over a corpus of files
+//! as the decoder grows features.
+
+use std::{io::Cursor, panic};
+
+use reference_png::{BitDepth, ColorType, Decoder, Transformations};
+
+use crate::{
+    intermediate::{PhysicalDimensions, Unit},
+    panic_message,
+    parser::PngParser,
+    Color, Png,
+};
+
+/// A difference between this crate and the reference decoder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Only this crate failed to decode the image
+    OursFailed(String),
+    /// Only the reference decoder failed to decode the image
+    ReferenceFailed(String),
+    /// Width and height differ
+    Dimensions {
+        ours: (u32, u32),
+        reference: (u32, u32),
+    },
+    /// Some pixels differ. Holds the number of differing pixels and the
+    /// position and colors of the first one
+    Pixels {
+        count: usize,
+        first: (u32, u32),
+        ours: Color,
+        reference: Color,
+    },
+    /// The pHYs chunk was read differently
+    PhysicalDimensions {
+        ours: Option<PhysicalDimensions>,
+        reference: Option<PhysicalDimensions>,
+    },
+}
+
+/// Decodes `bytes` with both decoders and returns all mismatches. An image
+/// both decoders reject counts as a match. A panic in this crate's decoder is
+/// caught and reported as [`Mismatch::OursFailed`], so a corpus run can carry
+/// on past it.
+pub fn compare(bytes: &[u8]) -> Vec<Mismatch> {
+    let ours = panic::catch_unwind(|| PngParser::new(Cursor::new(bytes))?.parse())
+        .unwrap_or_else(|payload| Err(std::io::Error::other(panic_message(payload))));
+    let (ours, reference) = match (ours, decode_reference(bytes)) {
+        (Ok(ours), Ok(reference)) => (ours, reference),
+        (Err(_), Err(_)) => return Vec::new(),
+        (Err(e), Ok(_)) => return vec![Mismatch::OursFailed(e.to_string())],
+        (Ok(_), Err(e)) => return vec![Mismatch::ReferenceFailed(e)],
+    };
+
+    let mut mismatches = Vec::new();
+    let size = |png: &Png| (png.width(), png.height());
+    if size(&ours) != size(&reference) {
+        mismatches.push(Mismatch::Dimensions {
+            ours: size(&ours),
+            reference: size(&reference),
+        });
+    } else {
+        let width = ours.width() as usize;
+        let mut differing = ours
+            .pixels()
+            .zip(reference.pixels())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b);
+        if let Some((i, (&a, &b))) = differing.next() {
+            mismatches.push(Mismatch::Pixels {
+                count: differing.count() + 1,
+                first: ((i % width) as u32, (i / width) as u32),
+                ours: a,
+                reference: b,
+            });
+        }
+    }
+
+    if ours.physical_dimensions() != reference.physical_dimensions() {
+        mismatches.push(Mismatch::PhysicalDimensions {
+            ours: ours.physical_dimensions(),
+            reference: reference.physical_dimensions(),
+        });
+    }
+
+    mismatches
+}
+
+/// Decodes `bytes` with the reference decoder, expanding every color type
+/// to at least 8 bit grey or truecolor
+fn decode_reference(bytes: &[u8]) -> Result<Png, String> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let data = &buffer[..frame.buffer_size()];
+
+    let samples: Vec<u16> = match frame.bit_depth {
+        BitDepth::Sixteen => data
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect(),
+        BitDepth::Eight => data.iter().map(|&b| b as u16 * 257).collect(),
+        _ => return Err("Reference decoder did not expand to 8 bits".to_string()),
+    };
+    let pixels = match frame.color_type {
+        ColorType::Grayscale => samples
+            .iter()
+            .map(|&v| Color::new_opaque(v, v, v))
+            .collect(),
+        ColorType::GrayscaleAlpha => samples
+            .chunks_exact(2)
+            .map(|s| Color::new(s[0], s[0], s[0], s[1]))
+            .collect(),
+        ColorType::Rgb => samples
+            .chunks_exact(3)
+            .map(|s| Color::new_opaque(s[0], s[1], s[2]))
+            .collect(),
+        ColorType::Rgba => samples
+            .chunks_exact(4)
+            .map(|s| Color::new(s[0], s[1], s[2], s[3]))
+            .collect(),
+        ColorType::Indexed => return Err("Reference decoder did not expand palette".to_string()),
+    };
+
+    let physical = reader.info().pixel_dims.map(|dims| PhysicalDimensions {
+        x: dims.xppu,
+        y: dims.yppu,
+        unit: match dims.unit {
+            reference_png::Unit::Unspecified => Unit::Unknown,
+            reference_png::Unit::Meter => Unit::Meter,
+        },
+    });
+
+    Png::try_new(frame.height, frame.width, pixels)
+        .map(|png| png.with_physical_dimensions(physical))
+        .map_err(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{PALETTE_PNG, TINY_PNG};
+
+    const PHYS_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0xd1,
+        0x49, 0x20, 0x56, 0x00, 0x00, 0x00, 0x09, 0x70, 0x48, 0x59, 0x73, 0x00, 0x00, 0x0e, 0xc4,
+        0x00, 0x00, 0x0e, 0xc4, 0x01, 0x95, 0x2b, 0x0e, 0x1b, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44,
+        0x41, 0x54, 0x78, 0x9c, 0x63, 0x60, 0xf8, 0x0f, 0x00, 0x01, 0x02, 0x01, 0x00, 0x42, 0xbe,
+        0xbc, 0x68, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    /// Grey black and white pixels, with black made transparent by tRNS,
+    /// which this crate doesn't support yet
+    const TRANSPARENT_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0xd1,
+        0x49, 0x20, 0x56, 0x00, 0x00, 0x00, 0x02, 0x74, 0x52, 0x4e, 0x53, 0x00, 0x00, 0x76, 0x93,
+        0xcd, 0x38, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60, 0xf8,
+        0x0f, 0x00, 0x01, 0x02, 0x01, 0x00, 0x42, 0xbe, 0xbc, 0x68, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    /// 3x3 grey, Adam7 interlaced
+    const INTERLACED_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x08, 0x00, 0x00, 0x00, 0x01, 0x04,
+        0x44, 0xda, 0xf5, 0x00, 0x00, 0x00, 0x17, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x10,
+        0x61, 0xb0, 0x61, 0xe8, 0xd9, 0xc2, 0xa0, 0xc1, 0xb0, 0x80, 0x21, 0x20, 0xa5, 0x02, 0x00,
+        0x16, 0x2b, 0x03, 0x85, 0x9d, 0x0e, 0xff, 0xb2, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e,
+        0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(compare(TINY_PNG), []);
+        assert_eq!(compare(PHYS_PNG), []);
+        assert_eq!(compare(INTERLACED_PNG), []);
+        assert_eq!(compare(&TINY_PNG[..20]), []);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert!(matches!(
+            &compare(PALETTE_PNG)[..],
+            [Mismatch::OursFailed(_)]
+        ));
+
+        assert_eq!(
+            compare(TRANSPARENT_PNG),
+            [Mismatch::Pixels {
+                count: 1,
+                first: (0, 0),
+                ours: Color::new_opaque(0, 0, 0),
+                reference: Color::new(0, 0, 0, 0),
+            }]
+        );
+    }
+}
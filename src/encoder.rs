@@ -0,0 +1,228 @@
+//! Writes a [`Png`] as a png file, picking the encoding settings from the
+//! image. The reverse of [`parser`](crate::parser).
+
+use std::io::{self, ErrorKind, Write};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    intermediate::{
+        self,
+        filter::{Adaptive, FilterKind},
+        Chunk, ColorKind, PngColor, PNG_SIG,
+    },
+    Color, Png,
+};
+
+/// Most bytes of compressed image data written per IDAT chunk
+const IDAT_SIZE: usize = 1 << 16;
+
+/// Encodes `png` with the smallest color type and bit depth that hold every
+/// pixel exactly, the filter type that suits each scanline best and default
+/// compression. Physical dimensions are written as a pHYs chunk.
+pub fn encode(png: &Png, mut writer: impl Write) -> io::Result<()> {
+    if png.width() == 0 || png.height() == 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "Image dimensions out of range",
+        ));
+    }
+
+    let kind = color_kind(png);
+    let depth = match kind {
+        ColorKind::Grey(false) => png.bit_depth(),
+        _ => png.bit_depth().max(8),
+    };
+    let color = PngColor::new(kind, depth).expect("Depth is allowed for the color kind");
+
+    let mut header = png.width().to_be_bytes().to_vec();
+    header.extend_from_slice(&png.height().to_be_bytes());
+    // Compression, filter and interlace methods are all 0
+    header.extend_from_slice(&[depth, kind.into(), 0, 0, 0]);
+
+    writer.write_all(&PNG_SIG)?;
+    Chunk::new(intermediate::IHDR, header.into()).write(&mut writer)?;
+    if let Some(physical) = png.physical_dimensions() {
+        Chunk::new(intermediate::PHYS, physical.to_bytes().into()).write(&mut writer)?;
+    }
+
+    let mut data = compress(png, &color)?;
+    let result = data
+        .chunks(IDAT_SIZE)
+        .try_for_each(|idat| Chunk::new(intermediate::IDAT, idat.into()).write(&mut writer));
+    crate::wipe(&mut data);
+    result?;
+    Chunk::new(intermediate::IEND, Box::default()).write(&mut writer)
+}
+
+/// Grey if every pixel is, with alpha if any pixel isn't opaque
+fn color_kind(png: &Png) -> ColorKind {
+    let grey = png
+        .pixels()
+        .all(|c| c.red() == c.green() && c.green() == c.blue());
+    let alpha = png.pixels().any(|c| c.alpha() != u16::MAX);
+    if grey {
+        ColorKind::Grey(alpha)
+    } else {
+        ColorKind::True(alpha)
+    }
+}
+
+/// Filters and compresses the scanlines of `png`, stored as `color`
+fn compress(png: &Png, color: &PngColor) -> io::Result<Vec<u8>> {
+    let width = png.width() as usize;
+    let bpp = color.data_len().div_ceil(8);
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+    let mut line = Vec::new();
+    let mut prev = vec![0; (width * color.data_len()).div_ceil(8)];
+    let (mut filtered, mut best) = (Vec::new(), Vec::new());
+    let mut result = Ok(());
+    for row in png.pixels.chunks(width) {
+        line.clear();
+        pack(row, color, &mut line);
+
+        // Pick the filter type with the smallest sum of absolute differences,
+        // the heuristic suggested by the standard
+        let mut best_kind = FilterKind::None;
+        let mut best_score = u64::MAX;
+        for kind in FilterKind::ALL {
+            Adaptive::filter(kind, &line, &prev, bpp, &mut filtered);
+            let score = filtered
+                .iter()
+                .map(|&b| (b as i8).unsigned_abs() as u64)
+                .sum();
+            if score < best_score {
+                (best_kind, best_score) = (kind, score);
+                std::mem::swap(&mut filtered, &mut best);
+            }
+        }
+
+        result = encoder
+            .write_all(&[best_kind as u8])
+            .and_then(|()| encoder.write_all(&best));
+        if result.is_err() {
+            break;
+        }
+        std::mem::swap(&mut line, &mut prev);
+    }
+    for buffer in [&mut line, &mut prev, &mut filtered, &mut best] {
+        crate::wipe(buffer);
+    }
+
+    let data = encoder.finish();
+    result.and(data)
+}
+
+/// Appends `row` to `line` as samples of `color`, packed most significant
+/// bit first. Channels are narrowed by dropping their low bits, which are
+/// replicas of the high bits for images of that depth.
+fn pack(row: &[Color], color: &PngColor, line: &mut Vec<u8>) {
+    let depth = color.depth();
+    // Bits used in the last byte of `line`
+    let mut used = 0;
+    for c in row {
+        let samples = match color.kind() {
+            ColorKind::Grey(false) => &[c.red()][..],
+            ColorKind::Grey(true) => &[c.red(), c.alpha()],
+            ColorKind::True(false) => &[c.red(), c.green(), c.blue()],
+            _ => &[c.red(), c.green(), c.blue(), c.alpha()],
+        };
+        for &sample in samples {
+            match depth {
+                16 => line.extend_from_slice(&sample.to_be_bytes()),
+                8 => line.push((sample >> 8) as u8),
+                _ => {
+                    if used == 0 {
+                        line.push(0);
+                    }
+                    let last = line.last_mut().expect("Pushed above");
+                    *last |= ((sample >> (16 - depth)) as u8) << (8 - depth - used);
+                    used = (used + depth) % 8;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{PhysicalDimensions, Unit};
+
+    fn round_trip(png: &Png) -> (Png, ColorKind, u8) {
+        let mut encoded = Vec::new();
+        encode(png, &mut encoded).unwrap();
+        let parser = crate::parser::PngParser::from_bytes(&encoded).unwrap();
+        let (kind, depth) = (parser.color_kind(), parser.bit_depth());
+        (parser.parse().unwrap(), kind, depth)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let grey = |v: u16| Color::new_opaque(v, v, v);
+        let cases = [
+            (
+                vec![grey(0), grey(u16::MAX), grey(0)],
+                ColorKind::Grey(false),
+                1,
+            ),
+            (
+                vec![grey(0x5555), grey(0), grey(0xaaaa)],
+                ColorKind::Grey(false),
+                2,
+            ),
+            (
+                vec![grey(0x1212), grey(0x3434), grey(0)],
+                ColorKind::Grey(false),
+                8,
+            ),
+            (
+                vec![grey(0x1234), grey(0), grey(0)],
+                ColorKind::Grey(false),
+                16,
+            ),
+            (
+                vec![Color::new(0, 0, 0, 0), grey(0), grey(0)],
+                ColorKind::Grey(true),
+                8,
+            ),
+            (
+                vec![Color::new_opaque(0xffff, 0, 0), grey(0), grey(0)],
+                ColorKind::True(false),
+                8,
+            ),
+            (
+                vec![Color::new(0x1234, 0, 0, 0x5678), grey(0), grey(0)],
+                ColorKind::True(true),
+                16,
+            ),
+        ];
+        for (row, kind, depth) in cases {
+            // Rows differ so every filter type gets a chance
+            let pixels = [row.clone(), row.iter().rev().copied().collect(), row].concat();
+            let png = Png::new(3, 3, pixels);
+            assert_eq!(round_trip(&png), (png, kind, depth));
+        }
+    }
+
+    #[test]
+    fn test_encode_physical() {
+        let physical = PhysicalDimensions {
+            x: 2835,
+            y: 2835,
+            unit: Unit::Meter,
+        };
+        let png = Png::new(1, 2, vec![Color::new_opaque(0, 0, 0); 2])
+            .with_physical_dimensions(Some(physical));
+        let (decoded, ..) = round_trip(&png);
+        assert_eq!(decoded.physical_dimensions(), Some(physical));
+    }
+
+    #[test]
+    fn test_encode_empty() {
+        let png = Png::new(0, 0, Vec::new());
+        let error = encode(&png, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{error::Error, fmt, io};
+
+/// Errors specific to the png format. Where an [`io::Error`] is returned,
+/// these are wrapped in one with kind [`io::ErrorKind::InvalidData`] and can
+/// be recovered with [`io::Error::get_ref`] and `downcast_ref`, or by
+/// converting back with `PngError::from`.
+#[derive(Debug)]
+pub enum PngError {
+    /// Another chunk came between two IDAT chunks, which must be consecutive
+    NonConsecutiveIdat,
+    /// The png was invalid in a way without its own variant, e.g. an unknown
+    /// compression method or a corrupt chunk. Holds the description
+    Format(String),
+    /// Reading failed, or the png ended early
+    Io(io::Error),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonConsecutiveIdat => write!(f, "IDAT chunks are not consecutive"),
+            Self::Format(description) => write!(f, "{description}"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for PngError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PngError> for io::Error {
+    fn from(value: PngError) -> Self {
+        match value {
+            PngError::Io(e) => e,
+            value => io::Error::new(io::ErrorKind::InvalidData, value),
+        }
+    }
+}
+
+/// Unwraps a [`PngError`] carried by the io error, if there is one. Other
+/// errors of kind [`io::ErrorKind::InvalidData`] become [`PngError::Format`]
+impl From<io::Error> for PngError {
+    fn from(value: io::Error) -> Self {
+        if !value.get_ref().is_some_and(|e| e.is::<PngError>()) {
+            return match value.kind() {
+                io::ErrorKind::InvalidData => Self::Format(value.to_string()),
+                _ => Self::Io(value),
+            };
+        }
+        let inner = value.into_inner().expect("Checked above");
+        *inner.downcast().expect("Checked above")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_round_trip() {
+        let error = PngError::from(io::Error::from(PngError::NonConsecutiveIdat));
+        assert!(matches!(error, PngError::NonConsecutiveIdat));
+
+        let error = PngError::from(io::Error::new(io::ErrorKind::InvalidData, "Bad chunk"));
+        assert!(matches!(&error, PngError::Format(e) if e == "Bad chunk"));
+        let error = io::Error::from(error);
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Bad chunk");
+
+        let error = PngError::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(matches!(&error, PngError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+}
@@ -2,20 +2,35 @@ pub mod chunk;
 pub mod chunk_kind;
 pub mod chunk_reader;
 pub mod color_kind;
+pub mod crc;
+pub mod exif;
 pub mod filter;
+pub mod iccp;
+pub mod interlace;
+pub mod keyword;
+pub mod mng;
+pub mod physical;
+pub mod preview;
+pub mod rewriter;
+pub mod slice_reader;
+pub mod xmp;
 
 use std::{
-    io::{self, Read},
-    iter,
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
 };
 
 pub use chunk::*;
 pub use chunk_kind::*;
 pub use color_kind::*;
+pub use crc::*;
+pub use interlace::*;
+pub use keyword::*;
+pub use physical::*;
 
-const PNG_SIG: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+pub(crate) const PNG_SIG: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-pub fn read_chunks(mut reader: impl Read) -> io::Result<Vec<Chunk>> {
+fn read_signature(reader: &mut impl Read) -> io::Result<()> {
     let mut sig = [0u8; 8];
     reader.read_exact(&mut sig)?;
     if sig != PNG_SIG {
@@ -24,23 +39,204 @@ pub fn read_chunks(mut reader: impl Read) -> io::Result<Vec<Chunk>> {
             "PNG missing signature",
         ));
     }
-    iter::from_fn(|| Some(Chunk::read(&mut reader)))
-        .take_while(|c| c.as_ref().is_ok_and(|c| c.kind() != chunk_kind::IEND))
-        .chain(iter::once(Ok(Chunk::new(chunk_kind::IEND, Box::new([])))))
-        .collect()
+    Ok(())
+}
+
+/// Reads every chunk up to and including IEND, checking their CRCs
+pub fn read_chunks(mut reader: impl Read) -> io::Result<Vec<Chunk>> {
+    read_signature(&mut reader)?;
+    let mut chunks = Vec::new();
+    loop {
+        let chunk = Chunk::read(&mut reader)?;
+        chunk.verify()?;
+        let end = chunk.kind() == chunk_kind::IEND;
+        chunks.push(chunk);
+        if end {
+            return Ok(chunks);
+        }
+    }
+}
+
+/// Counts the chunks of each kind in a PNG datastream, along with the total
+/// length of their data in bytes. Chunk data is skipped over rather than read,
+/// and CRCs are not checked.
+pub fn census(mut reader: impl Read + Seek) -> io::Result<HashMap<ChunkKind, (usize, u64)>> {
+    read_signature(&mut reader)?;
+
+    let mut census: HashMap<ChunkKind, (usize, u64)> = HashMap::new();
+    for_each_chunk_header(&mut reader, |kind, len| {
+        let entry = census.entry(kind).or_default();
+        entry.0 += 1;
+        entry.1 += len as u64;
+    })?;
+    Ok(census)
+}
+
+/// Features of a PNG that decide how it needs to be handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Has an acTL chunk, making it an APNG
+    pub animated: bool,
+    /// Has an alpha channel or a tRNS chunk
+    pub transparency: bool,
+    /// Uses Adam7 interlacing
+    pub interlaced: bool,
+    /// Has an embedded ICC profile (iCCP)
+    pub icc_profile: bool,
+    /// Has an sRGB chunk
+    pub srgb: bool,
+    /// Has EXIF data (eXIf)
+    pub exif: bool,
+    /// Has any textual data (tEXt, zTXt or iTXt)
+    pub text: bool,
+    /// Has a preview embedded by this crate. See [`preview::read_preview`]
+    pub preview: bool,
+}
+
+/// Finds the [`Capabilities`] of a PNG datastream without decoding it. Only
+/// the header chunk is read; all other chunk data is skipped over.
+pub fn probe(mut reader: impl Read + Seek) -> io::Result<Capabilities> {
+    read_signature(&mut reader)?;
+
+    let header = Chunk::read(&mut reader)?;
+    header.verify()?;
+    if header.kind() != chunk_kind::IHDR || header.len() != 13 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "PNG didn't start with expected header",
+        ));
+    }
+    let color_kind = ColorKind::try_from(header.data()[9])
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+    let mut capabilities = Capabilities {
+        transparency: matches!(color_kind, ColorKind::Grey(true) | ColorKind::True(true)),
+        interlaced: header.data()[12] == 1,
+        ..Default::default()
+    };
+    for_each_chunk_header(&mut reader, |kind, _| match kind {
+        chunk_kind::ACTL => capabilities.animated = true,
+        chunk_kind::TRNS => capabilities.transparency = true,
+        chunk_kind::ICCP => capabilities.icc_profile = true,
+        chunk_kind::SRGB => capabilities.srgb = true,
+        chunk_kind::EXIF => capabilities.exif = true,
+        chunk_kind::TEXT | chunk_kind::ZTXT | chunk_kind::ITXT => capabilities.text = true,
+        chunk_kind::PRVW => capabilities.preview = true,
+        _ => (),
+    })?;
+    Ok(capabilities)
+}
+
+/// Bytes of the stream [`resync`] holds at once while scanning
+const RESYNC_WINDOW: usize = 8 * 1024;
+
+/// Scans forward from the current position for the next plausible chunk, one
+/// with a valid kind, a length that fits in the rest of the stream and a
+/// matching crc, and seeks to its start. Used to recover from a corrupt
+/// chunk.
+///
+/// The stream is scanned through a fixed size window. Only candidates with a
+/// valid length and kind have their crc checked, by streaming the data
+/// through it.
+///
+/// Fails with [`ErrorKind::InvalidData`] if no chunk is found.
+pub fn resync(reader: &mut (impl Read + Seek)) -> io::Result<()> {
+    let mut pos = reader.stream_position()?;
+    let mut window = Vec::with_capacity(RESYNC_WINDOW);
+    loop {
+        reader.seek(SeekFrom::Start(pos))?;
+        window.clear();
+        reader
+            .by_ref()
+            .take(RESYNC_WINDOW as u64)
+            .read_to_end(&mut window)?;
+
+        // Every offset whose length and kind lie in the window
+        for (i, bound) in window.windows(8).enumerate() {
+            let len = u32::from_be_bytes(*bound.first_chunk::<4>().expect("8 > 4"));
+            let plausible = len <= MAX_CHUNK_LENGTH
+                && ChunkKind::try_from(bound[4..].first_chunk::<4>().expect("4 = 4")).is_ok();
+            if !plausible {
+                continue;
+            }
+            let start = pos + i as u64;
+            let matches = match crc_matches(reader, start, len) {
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
+                result => result?,
+            };
+            if matches {
+                reader.seek(SeekFrom::Start(start))?;
+                return Ok(());
+            }
+        }
+
+        if window.len() < RESYNC_WINDOW {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "No chunk found to resync to",
+            ));
+        }
+        // Overlap so lengths and kinds split across windows are checked
+        pos += (RESYNC_WINDOW - 7) as u64;
+    }
+}
+
+/// Whether the chunk at `start` with data length `len` ends in a matching
+/// crc. Fails with [`ErrorKind::UnexpectedEof`] if the stream ends first
+fn crc_matches(reader: &mut (impl Read + Seek), start: u64, len: u32) -> io::Result<bool> {
+    reader.seek(SeekFrom::Start(start + 4))?;
+    let mut crc = Crc32::new();
+    let mut buf = [0u8; 4096];
+    // Kind and data
+    let mut remaining = len as usize + 4;
+    while remaining > 0 {
+        let piece = &mut buf[..remaining.min(4096)];
+        reader.read_exact(piece)?;
+        crc.update(piece);
+        remaining -= piece.len();
+    }
+
+    let mut stored = [0u8; 4];
+    reader.read_exact(&mut stored)?;
+    Ok(u32::from_be_bytes(stored) == crc.finish())
+}
+
+/// Calls `f` with the kind and data length of each chunk up to and including
+/// IEND, seeking past the chunk data and crc.
+fn for_each_chunk_header(
+    reader: &mut (impl Read + Seek),
+    mut f: impl FnMut(ChunkKind, u32),
+) -> io::Result<()> {
+    loop {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        if len > MAX_CHUNK_LENGTH {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Chunk length too long",
+            ));
+        }
+
+        let mut kind = [0u8; 4];
+        reader.read_exact(&mut kind)?;
+        let kind =
+            ChunkKind::try_from(&kind).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+        reader.seek_relative(len as i64 + 4)?; // Skip data and crc
+
+        f(kind, len);
+
+        if kind == chunk_kind::IEND {
+            return Ok(());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    const TINY_PNG: &[u8] = &[
-        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
-        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x37,
-        0x6e, 0xf9, 0x24, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x63, 0x60,
-        0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x73, 0x75, 0x01, 0x18, 0x00, 0x00, 0x00, 0x00, 0x49,
-        0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
-    ];
+    use crate::test_util::{tiny_png_with, TINY_PNG};
 
     #[test]
     fn test_tiny() {
@@ -58,5 +254,65 @@ mod tests {
         ];
 
         assert_eq!(chunks[0], expected[0]);
+
+        let mut corrupt = TINY_PNG.to_vec();
+        corrupt[40] ^= 0xff;
+        assert!(read_chunks(&corrupt[..]).is_err());
+    }
+
+    #[test]
+    fn test_resync() {
+        // Start in the middle of the IHDR data
+        let mut reader = io::Cursor::new(TINY_PNG);
+        reader.set_position(20);
+        resync(&mut reader).unwrap();
+        assert_eq!(reader.position(), 33);
+
+        reader.set_position(50);
+        resync(&mut reader).unwrap();
+        assert_eq!(reader.position(), 55);
+
+        reader.set_position(56);
+        assert!(resync(&mut reader).is_err());
+
+        // Garbage that looks like the start of a huge chunk
+        let mut garbage = vec![0x7f, 0xff, 0x00, 0x00];
+        garbage.extend_from_slice(b"IDATjunk");
+        garbage.extend_from_slice(&TINY_PNG[8..]);
+        let mut reader = io::Cursor::new(&garbage);
+        resync(&mut reader).unwrap();
+        assert_eq!(reader.position(), 12);
+
+        // Chunks past the first window, including one split across two
+        for offset in [RESYNC_WINDOW - 4, 3 * RESYNC_WINDOW] {
+            let mut garbage = vec![0; offset];
+            garbage.extend_from_slice(&TINY_PNG[8..]);
+            let mut reader = io::Cursor::new(&garbage);
+            resync(&mut reader).unwrap();
+            assert_eq!(reader.position(), offset as u64);
+        }
+    }
+
+    #[test]
+    fn test_census() {
+        let census = census(io::Cursor::new(TINY_PNG)).expect("Valid png");
+        assert_eq!(census.len(), 3);
+        assert_eq!(census[&chunk_kind::IHDR], (1, 13));
+        assert_eq!(census[&chunk_kind::IDAT], (1, 10));
+        assert_eq!(census[&chunk_kind::IEND], (1, 0));
+    }
+
+    #[test]
+    fn test_probe() {
+        let capabilities = probe(io::Cursor::new(TINY_PNG)).expect("Valid png");
+        assert_eq!(capabilities, Capabilities::default());
+
+        let with_text = tiny_png_with([Chunk::new(
+            chunk_kind::TEXT,
+            b"Title\0Tiny".to_vec().into_boxed_slice(),
+        )]);
+        let capabilities = probe(io::Cursor::new(with_text)).expect("Valid png");
+        assert!(capabilities.text);
+        assert!(!capabilities.animated);
     }
 }
@@ -1,24 +1,39 @@
-use std::io::{self, ErrorKind, Read};
+use std::io::{self, ErrorKind, Read, Write};
 
-use super::ChunkKind;
+use super::{ChunkKind, Crc32};
 
-const MAX_CHUNK_LENGTH: u32 = 2u32.pow(31) - 1;
-pub(super) const CRC_TABLE: [u32; 256] = make_crc_table();
+pub(super) const MAX_CHUNK_LENGTH: u32 = 2u32.pow(31) - 1;
 
 // Should this deref to slice?
 // Should data be mutable?
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone)]
 pub struct Chunk {
     kind: ChunkKind,
     data: Box<[u8]>,
+    /// CRC read along with the chunk, not yet checked
+    stored_crc: Option<u32>,
 }
 
 impl Chunk {
     pub const fn new(kind: ChunkKind, data: Box<[u8]>) -> Self {
-        Chunk { kind, data }
+        Chunk {
+            kind,
+            data,
+            stored_crc: None,
+        }
     }
 
-    /// Reads chunk data from a buffered reader.
+    /// A chunk with the CRC it was stored with
+    pub(super) const fn with_stored_crc(kind: ChunkKind, data: Box<[u8]>, crc: u32) -> Self {
+        Chunk {
+            kind,
+            data,
+            stored_crc: Some(crc),
+        }
+    }
+
+    /// Reads chunk data from a buffered reader. The CRC is kept but not
+    /// checked; see [`Chunk::verify`].
     pub fn read(reader: &mut impl Read) -> io::Result<Self> {
         let mut len: [u8; 4] = [0; 4];
         reader.read_exact(&mut len)?;
@@ -43,21 +58,38 @@ impl Chunk {
         reader.read_exact(&mut crc)?;
         let crc: u32 = u32::from_be_bytes(crc);
 
-        let chunk = Self {
-            kind,
-            data: data.into(),
-        };
+        Ok(Self::with_stored_crc(kind, data.into(), crc))
+    }
 
-        let expected_crc = chunk.crc();
+    /// CRC the chunk was read with, if it was read rather than created
+    pub fn stored_crc(&self) -> Option<u32> {
+        self.stored_crc
+    }
 
-        if expected_crc != crc {
-            return Err(io::Error::new(
+    /// Checks the stored CRC against the chunk's kind and data. Chunks
+    /// without a stored CRC always pass
+    pub fn verify(&self) -> io::Result<()> {
+        match self.stored_crc {
+            Some(crc) if crc != self.crc() => Err(io::Error::new(
                 ErrorKind::InvalidData,
                 "Mismatched crc values",
-            ));
+            )),
+            _ => Ok(()),
         }
+    }
+
+    /// Writes the chunk to the writer, including its length and crc. A
+    /// stored CRC is written as is, without being recomputed.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&(self.data.len() as u32).to_be_bytes())?;
+        writer.write_all(self.kind.as_bytes())?;
+        writer.write_all(self.data())?;
+        writer.write_all(&self.written_crc().to_be_bytes())
+    }
 
-        Ok(chunk)
+    /// CRC [`Chunk::write`] writes: the stored one if there is one
+    fn written_crc(&self) -> u32 {
+        self.stored_crc.unwrap_or_else(|| self.crc())
     }
 
     /// Raw data of the chunk
@@ -70,6 +102,11 @@ impl Chunk {
         self.data.len()
     }
 
+    /// Whether the chunk has no data
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     /// Chunk type
     pub fn kind(&self) -> ChunkKind {
         self.kind
@@ -77,42 +114,30 @@ impl Chunk {
 
     /// Cyclic Redundancy Code for the chunk
     pub fn crc(&self) -> u32 {
-        // based off of https://www.w3.org/TR/png-3/#D-CRCAppendix
-        let mut crc = u32::MAX;
-        for &b in self.kind.as_bytes() {
-            let lookup_ind = (crc ^ b as u32) as usize & 0xff;
-            crc = CRC_TABLE[lookup_ind] ^ (crc >> 8);
-        }
-        for &b in self.data() {
-            let lookup_ind = (crc ^ b as u32) as usize & 0xff;
-            crc = CRC_TABLE[lookup_ind] ^ (crc >> 8);
-        }
-
-        crc ^ u32::MAX
+        chunk_crc(self.kind, self.data())
     }
 }
 
-const fn make_crc_table() -> [u32; 256] {
-    // based off of https://www.w3.org/TR/png-3/#D-CRCAppendix
-    let mut table = [0; 256];
-    let mut n = 0;
-    while n < 256 {
-        let mut c = n as u32;
-        let mut k = 0;
-        while k < 8 {
-            if c & 1 == 1 {
-                c = 0xedb88320u32 ^ (c >> 1);
-            } else {
-                c = c >> 1;
-            }
-            k += 1;
-        }
-        table[n] = c;
-        n += 1
+/// Cyclic Redundancy Code over a chunk's kind and data
+pub(super) fn chunk_crc(kind: ChunkKind, data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(kind.as_bytes());
+    crc.update(data);
+    crc.finish()
+}
+
+/// Chunks are equal if they are written the same: a chunk with a correct
+/// stored CRC equals one without, but not one with a corrupt CRC
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.data == other.data
+            && self.written_crc() == other.written_crc()
     }
-    table
 }
 
+impl Eq for Chunk {}
+
 impl std::fmt::Debug for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -123,3 +148,36 @@ impl std::fmt::Debug for Chunk {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::chunk_kind;
+
+    const IEND: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_stored_crc() {
+        let chunk = Chunk::read(&mut &IEND[..]).unwrap();
+        assert_eq!(chunk.stored_crc(), Some(0xae426082));
+        assert!(chunk.verify().is_ok());
+        assert_eq!(chunk, Chunk::new(chunk_kind::IEND, Box::new([])));
+        assert_eq!(
+            Chunk::new(chunk_kind::IEND, Box::new([])).stored_crc(),
+            None
+        );
+
+        let mut corrupt = IEND.to_vec();
+        corrupt[11] ^= 0xff;
+        let chunk = Chunk::read(&mut &corrupt[..]).unwrap();
+        assert!(chunk.verify().is_err());
+        assert_ne!(chunk, Chunk::new(chunk_kind::IEND, Box::new([])));
+
+        // The stored crc is copied as is
+        let mut written = Vec::new();
+        chunk.write(&mut written).unwrap();
+        assert_eq!(written, corrupt);
+    }
+}
@@ -3,6 +3,31 @@ pub const PLTE: ChunkKind = ChunkKind(*b"PLTE");
 pub const IDAT: ChunkKind = ChunkKind(*b"IDAT");
 pub const IEND: ChunkKind = ChunkKind(*b"IEND");
 
+pub const TRNS: ChunkKind = ChunkKind(*b"tRNS");
+pub const GAMA: ChunkKind = ChunkKind(*b"gAMA");
+pub const CHRM: ChunkKind = ChunkKind(*b"cHRM");
+pub const CICP: ChunkKind = ChunkKind(*b"cICP");
+pub const MDCV: ChunkKind = ChunkKind(*b"mDCV");
+pub const CLLI: ChunkKind = ChunkKind(*b"cLLI");
+pub const SBIT: ChunkKind = ChunkKind(*b"sBIT");
+pub const ICCP: ChunkKind = ChunkKind(*b"iCCP");
+pub const SRGB: ChunkKind = ChunkKind(*b"sRGB");
+pub const TEXT: ChunkKind = ChunkKind(*b"tEXt");
+pub const ZTXT: ChunkKind = ChunkKind(*b"zTXt");
+pub const ITXT: ChunkKind = ChunkKind(*b"iTXt");
+pub const EXIF: ChunkKind = ChunkKind(*b"eXIf");
+pub const TIME: ChunkKind = ChunkKind(*b"tIME");
+pub const PHYS: ChunkKind = ChunkKind(*b"pHYs");
+pub const ACTL: ChunkKind = ChunkKind(*b"acTL");
+
+// Private chunks used by this crate
+pub const PRVW: ChunkKind = ChunkKind(*b"prVW");
+
+// MNG and JNG
+pub const MHDR: ChunkKind = ChunkKind(*b"MHDR");
+pub const MEND: ChunkKind = ChunkKind(*b"MEND");
+pub const JHDR: ChunkKind = ChunkKind(*b"JHDR");
+
 const SIG_BIT: u8 = 0b100000;
 
 /// Specifies the type of chunk. Should maybe be enum with Unkown variant?
@@ -13,7 +38,7 @@ const SIG_BIT: u8 = 0b100000;
 /// IDAT
 /// IEND
 /// Others are optional
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChunkKind([u8; 4]);
 
 impl ChunkKind {
@@ -59,16 +84,30 @@ impl std::fmt::Debug for ChunkKind {
     }
 }
 
+impl std::fmt::Display for ChunkKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::str::from_utf8(&self.0).expect("Always valid ascii characters"))
+    }
+}
+
+impl std::str::FromStr for ChunkKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes: &[u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| "Chunk type must be 4 characters")?;
+        Self::try_from(bytes)
+    }
+}
+
 impl TryFrom<&[u8; 4]> for ChunkKind {
     type Error = &'static str; // TODO: better error type
 
     fn try_from(value: &[u8; 4]) -> Result<Self, Self::Error> {
-        // TODO: Check validity:
         // Should be ascii characters (65-90, 97-122)
-        if value
-            .iter()
-            .all(|&v| (v >= 65 && v <= 90) || (v >= 97 || v <= 122))
-        {
+        if value.iter().all(u8::is_ascii_alphabetic) {
             Ok(Self(*value))
         } else {
             Err("Invalid chunk type")
@@ -110,4 +149,21 @@ mod tests {
         assert!(!e2.public());
         assert!(!e2.copy_safe());
     }
+
+    #[test]
+    fn test_string_conversion() {
+        assert_eq!(IHDR.to_string(), "IHDR");
+        assert_eq!("tEXt".parse::<ChunkKind>(), Ok(TEXT));
+        assert!("tEX".parse::<ChunkKind>().is_err());
+        assert!("tEXt1".parse::<ChunkKind>().is_err());
+        assert!("tEX1".parse::<ChunkKind>().is_err());
+        assert!("tEXé".parse::<ChunkKind>().is_err());
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut kinds = [TEXT, IHDR, IDAT, GAMA];
+        kinds.sort();
+        assert_eq!(kinds, [IDAT, IHDR, GAMA, TEXT]);
+    }
 }
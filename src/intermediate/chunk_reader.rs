@@ -1,10 +1,10 @@
 use std::io::{self, ErrorKind, Read};
 
-use super::{chunk_kind, ChunkKind, CRC_TABLE};
+use super::{chunk_kind, ChunkKind, Crc32};
+use crate::PngError;
 
 /// Bytes for CRC + length + kind
 const BOUND_LEN: usize = 12;
-const INITIAL_CRC: u32 = 3394304481;
 
 /// Lazily parses data chunks of a PNG datastream
 #[derive(Debug)]
@@ -13,12 +13,26 @@ pub struct ChunkReader<R> {
     /// Remaining bytes in current chunk
     leftover: usize,
     /// CRC of current chunk calculated on the fly
-    crc: u32,
+    crc: Crc32,
+    /// IEND was reached, or reading failed
+    done: bool,
+    /// Skip chunks between IDAT chunks instead of failing
+    lenient: bool,
+    /// A chunk other than IDAT was skipped since the last IDAT
+    skipped: bool,
 }
 
 impl<R> ChunkReader<R> {
     pub fn is_done(&self) -> bool {
-        self.leftover == 0
+        self.done
+    }
+
+    /// Allows other chunks between IDAT chunks, which the standard forbids.
+    /// They are skipped. Otherwise reading fails with
+    /// [`PngError::NonConsecutiveIdat`].
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
     }
 }
 
@@ -26,109 +40,111 @@ impl<R: Read> ChunkReader<R> {
     pub fn new(mut reader: R) -> std::io::Result<Self> {
         let mut len: [u8; 4] = [0; 4];
         reader.read_exact(&mut len)?;
-        let mut len = u32::from_be_bytes(len) as usize;
+        let len = u32::from_be_bytes(len) as usize;
 
         let mut kind: [u8; 4] = [0; 4];
         reader.read_exact(&mut kind)?;
         let kind =
             ChunkKind::try_from(&kind).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-        match kind {
-            chunk_kind::IDAT => (),
-            chunk_kind::IEND => {
-                // should be 0 anyway
-                len = 0;
+        let done = match kind {
+            chunk_kind::IDAT => false,
+            chunk_kind::IEND => true,
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Image data must start with an IDAT chunk",
+                ))
             }
-            c => panic!("Unexpected chunk kind in chunk reader: {:?}", c),
-        }
+        };
 
+        let mut crc = Crc32::new();
+        crc.update(kind.as_bytes());
         Ok(Self {
             reader,
-            leftover: len,
-            crc: INITIAL_CRC,
+            leftover: if done { 0 } else { len },
+            crc,
+            done,
+            lenient: false,
+            skipped: false,
         })
     }
-}
 
-impl<R: Read> Read for ChunkReader<R> {
-    // Right now violates the error condition in the docs:
-    // If this function encounters any form of I/O or other error, an error
-    // variant will be returned. If an error is returned then it must be
-    // guaranteed that no bytes were read.
-    //
-    // The easiest way I can see to fix this would be to buffer it and save and
-    //restore the cursor position in case of an error
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        // Done reading. IEND recieved
-        if self.leftover == 0 {
-            return Ok(0);
+    /// Checks the crc of the current chunk and moves on to the next one,
+    /// skipping chunks that aren't IDAT.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let mut bound = [0u8; BOUND_LEN];
+        self.reader.read_exact(&mut bound)?;
+
+        let found_crc = u32::from_be_bytes(*bound.first_chunk::<4>().expect("12 > 4"));
+        if found_crc != self.crc.finish() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Mismatched crc. Error somewhere in transit/processing",
+            ));
         }
 
-        let mut bc = self.reader.read(buf)?;
-        let mut used = 0;
-        while self.leftover != 0 && bc - used >= self.leftover {
-            let cb_start = self.leftover + used;
-            let cb_end = (cb_start + BOUND_LEN).min(bc);
-            let to_read = BOUND_LEN.saturating_sub(bc - cb_start);
-
-            let mut chunk_bound = [0u8; BOUND_LEN];
-            // Fill what we can from already read chunks
-            buf[cb_start..cb_end]
-                .iter()
-                .zip(chunk_bound.iter_mut())
-                .for_each(|(o, b)| *b = *o);
-            // Get the rest from the reader
-            if to_read > 0 {
-                self.reader
-                    .read_exact(&mut chunk_bound[BOUND_LEN - to_read..])?;
-            }
-
-            // Move the chunk boundary to the end
-            buf[cb_start..bc].rotate_left(BOUND_LEN - to_read);
-            buf[bc - (cb_end - cb_start)..bc].fill(0); // Probably not necessary, but clean up
+        let len = u32::from_be_bytes(*bound[4..].first_chunk::<4>().expect("8 > 4"));
+        let kind = ChunkKind::try_from(bound[8..].first_chunk::<4>().expect("4 = 4"))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        self.crc = Crc32::new();
+        self.crc.update(kind.as_bytes());
 
-            // Adjust the byte count
-            bc -= cb_end - cb_start;
-
-            // Update the crc and check it
-            for &b in &buf[used..cb_start] {
-                let lookup_ind = (self.crc ^ b as u32) as usize & 0xff;
-                self.crc = CRC_TABLE[lookup_ind] ^ (self.crc >> 8);
+        match kind {
+            chunk_kind::IDAT if self.skipped && !self.lenient => {
+                Err(PngError::NonConsecutiveIdat.into())
             }
-            let found_crc = u32::from_be_bytes(*chunk_bound.first_chunk::<4>().expect("12 > 4"));
-            if found_crc != self.crc ^ u32::MAX {
-                // Could this be recoverable?
-                self.leftover = 0;
-                return Err(io::Error::new(
-                    ErrorKind::InvalidData,
-                    "Mismatched crc. Error somewhere in transit/processing",
-                ));
+            chunk_kind::IDAT => {
+                self.leftover = len as usize;
+                self.skipped = false;
+                Ok(())
             }
-
-            // Reset the leftover and crc
-            used += self.leftover;
-            self.crc = INITIAL_CRC;
-            self.leftover =
-                u32::from_be_bytes(*chunk_bound[4..].first_chunk::<4>().expect("8 > 4")) as usize;
-            let kind = ChunkKind::try_from(chunk_bound[8..].first_chunk::<4>().expect("4 = 4"))
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-            match kind {
-                chunk_kind::IDAT => (),
-                chunk_kind::IEND => {
-                    // should be 0 anyway
-                    self.leftover = 0;
-                    bc = used; // cut off IEND length and crc
+            chunk_kind::IEND => {
+                self.done = true;
+                Ok(())
+            }
+            _ => {
+                // Read through the chunk, in case image data follows it. Its
+                // crc is checked with the next chunk
+                let mut data = Vec::new();
+                (&mut self.reader).take(len as u64).read_to_end(&mut data)?;
+                if data.len() != len as usize {
+                    return Err(io::Error::new(ErrorKind::UnexpectedEof, "Chunk cut off"));
                 }
-                c => panic!("Unexpected chunk kind in chunk reader: {:?}", c),
+                self.crc.update(&data);
+                self.skipped = true;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    // Reads stop at chunk boundaries, so no bytes are read if moving on to the
+    // next chunk fails
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.leftover == 0 {
+            // Done reading. IEND recieved
+            if self.done {
+                return Ok(0);
+            }
+            if let Err(e) = self.next_chunk() {
+                self.done = true;
+                return Err(e);
             }
         }
 
-        // update crc with remaining bytes
-        for &b in &buf[used..bc] {
-            let lookup_ind = (self.crc ^ b as u32) as usize & 0xff;
-            self.crc = CRC_TABLE[lookup_ind] ^ (self.crc >> 8);
+        let len = buf.len().min(self.leftover);
+        let bc = self.reader.read(&mut buf[..len])?;
+        if bc == 0 && len > 0 {
+            self.done = true;
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "IDAT chunk cut off",
+            ));
         }
 
-        self.leftover -= bc - used;
+        self.crc.update(&buf[..bc]);
+        self.leftover -= bc;
         Ok(bc)
     }
 }
@@ -136,6 +152,7 @@ impl<R: Read> Read for ChunkReader<R> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::intermediate::Chunk;
 
     const SINGLE_CHUNK: &[u8] = &[
         0x00, 0x00, 0x00, 0x0a, // len
@@ -171,6 +188,46 @@ mod tests {
         assert_eq!(data[..], SINGLE_CHUNK[8..18]);
     }
 
+    /// Two IDAT chunks of `MULTI_CHUNK` with a tEXt chunk between them
+    fn split_idat() -> Vec<u8> {
+        let mut data = MULTI_CHUNK[..22].to_vec();
+        Chunk::new(chunk_kind::TEXT, b"Title\0Split".to_vec().into())
+            .write(&mut data)
+            .unwrap();
+        data.extend_from_slice(&MULTI_CHUNK[22..]);
+        data
+    }
+
+    #[test]
+    fn test_trailing_chunk() {
+        let mut data = SINGLE_CHUNK[..22].to_vec();
+        Chunk::new(chunk_kind::TEXT, b"Title\0After".to_vec().into())
+            .write(&mut data)
+            .unwrap();
+        data.extend_from_slice(&SINGLE_CHUNK[22..]);
+
+        let mut reader = ChunkReader::new(&data[..]).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(reader.read_to_end(&mut out).unwrap(), 10);
+        assert!(reader.is_done());
+    }
+
+    #[test]
+    fn test_non_consecutive_idat() {
+        let data = split_idat();
+        let error = ChunkReader::new(&data[..])
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let error = error.get_ref().and_then(|e| e.downcast_ref::<PngError>());
+        assert!(matches!(error, Some(PngError::NonConsecutiveIdat)));
+
+        let mut reader = ChunkReader::new(&data[..]).unwrap().lenient(true);
+        let mut out = Vec::new();
+        assert_eq!(reader.read_to_end(&mut out).unwrap(), 30);
+        assert_eq!(out[10..20], MULTI_CHUNK[30..40]);
+    }
+
     #[test]
     fn test_multi_chunk() {
         let mut reader = ChunkReader::new(MULTI_CHUNK).unwrap();
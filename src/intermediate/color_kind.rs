@@ -1,5 +1,44 @@
 use crate::Color;
 
+// Lookup tables scaling raw samples of each bit depth to 16 bits
+const SCALE_1: [u16; 2] = scale_table(1);
+const SCALE_2: [u16; 4] = scale_table(2);
+const SCALE_4: [u16; 16] = scale_table(4);
+const SCALE_8: [u16; 256] = scale_table(8);
+
+// Lookup tables scaling raw samples below 8 bits to 8 bits
+const SCALE_1_TO_8: [u8; 2] = narrow_table(SCALE_1);
+const SCALE_2_TO_8: [u8; 4] = narrow_table(SCALE_2);
+const SCALE_4_TO_8: [u8; 16] = narrow_table(SCALE_4);
+
+/// Scales every `depth` bit value to 16 bits by replicating its bits
+const fn scale_table<const N: usize>(depth: u8) -> [u16; N] {
+    let mut table = [0; N];
+    let mut i = 0;
+    while i < N {
+        let mut value = i as u16;
+        let mut t = depth;
+        while t < 16 {
+            value |= value << t;
+            t *= 2;
+        }
+        table[i] = value;
+        i += 1;
+    }
+    table
+}
+
+/// Keeps the high byte of each entry. Replicated bits make this exact
+const fn narrow_table<const N: usize>(wide: [u16; N]) -> [u8; N] {
+    let mut table = [0; N];
+    let mut i = 0;
+    while i < N {
+        table[i] = (wide[i] >> 8) as u8;
+        i += 1;
+    }
+    table
+}
+
 pub struct PngColor {
     kind: ColorKind,
     depth: u8,
@@ -14,6 +53,14 @@ impl PngColor {
         Ok(Self { kind, depth })
     }
 
+    pub const fn kind(&self) -> ColorKind {
+        self.kind
+    }
+
+    pub const fn depth(&self) -> u8 {
+        self.depth
+    }
+
     pub const fn channels(&self) -> u8 {
         self.kind.channels()
     }
@@ -33,46 +80,105 @@ impl PngColor {
         self.channels() as usize * self.depth as usize
     }
 
+    /// Reads channel `c` of pixel `i` for depths below 16 bits, unscaled.
+    /// Samples are packed most significant bit first
+    fn raw_sample(&self, data: &[u8], i: usize, c: usize) -> usize {
+        let start_bit = (i * self.data_len()) + c * self.depth as usize;
+        let shift = 8 - self.depth as usize - start_bit % 8;
+        (data[start_bit / 8] >> shift) as usize & self.channel_mask() as usize
+    }
+
+    /// Reads channel `c` of pixel `i`, scaled to 16 bits
+    fn sample(&self, data: &[u8], i: usize, c: usize) -> u16 {
+        if self.depth == 16 {
+            let byte = (i * self.channels() as usize + c) * 2;
+            return u16::from_be_bytes([data[byte], data[byte + 1]]);
+        }
+        let raw = self.raw_sample(data, i, c);
+        match self.depth {
+            8 => SCALE_8[raw],
+            4 => SCALE_4[raw],
+            2 => SCALE_2[raw],
+            _ => SCALE_1[raw],
+        }
+    }
+
+    /// Reads channel `c` of pixel `i`, scaled to 8 bits. Only for depths of
+    /// 8 bits or less
+    fn sample_8(&self, data: &[u8], i: usize, c: usize) -> u8 {
+        let raw = self.raw_sample(data, i, c);
+        match self.depth {
+            8 => raw as u8,
+            4 => SCALE_4_TO_8[raw],
+            2 => SCALE_2_TO_8[raw],
+            _ => SCALE_1_TO_8[raw],
+        }
+    }
+
     pub fn parse(&self, data: &[u8]) -> Result<Vec<Color>, &'static str> {
-        // Not sure how to handle bit depths < 8 (1,2,4)
         let mut colors = Vec::new();
         for i in 0..data.len() * 8 / self.data_len() {
-            // i = starting bit position of color
-            let mut raw: Vec<u16> = Vec::new();
-            for c in (0..self.channels()).rev() {
-                // higher shift first
-                let start_bit = (i * self.data_len()) + (c * self.depth) as usize;
-                let u16_to_check = start_bit / 16;
-                let shift = start_bit % 16;
-                let mask = self.channel_mask() << shift;
-
-                // Not necessarily even in length (evenly divides into u16s)
-                let d = u16::from_be_bytes(
-                    *data[u16_to_check..]
-                        .first_chunk::<2>()
-                        .unwrap_or(&[data[u16_to_check], 0]),
-                );
-                let mut channel = (d & mask) >> shift;
-                let mut t = self.depth;
-                while t < 16 {
-                    channel |= channel << t;
-                    t *= 2;
-                }
-                raw.push(channel)
+            let mut raw = [0u16; 4];
+            for (c, channel) in raw[..self.channels() as usize].iter_mut().enumerate() {
+                *channel = self.sample(data, i, c);
             }
             match self.kind {
                 ColorKind::Grey(false) => colors.push(Color::new(raw[0], raw[0], raw[0], u16::MAX)),
                 ColorKind::Grey(true) => colors.push(Color::new(raw[0], raw[0], raw[0], raw[1])),
                 ColorKind::True(false) => colors.push(Color::new(raw[0], raw[1], raw[2], u16::MAX)),
                 ColorKind::True(true) => colors.push(Color::new(raw[0], raw[1], raw[2], raw[3])),
-                ColorKind::Indexed => todo!(),
+                ColorKind::Indexed => return Err("Indexed color is not supported yet"),
             }
         }
         Ok(colors)
     }
+
+    /// Like [`PngColor::parse`], but gives 8 bit rgba. Samples of 8 bits or
+    /// less fit exactly, so 16 bit samples are rejected
+    pub fn parse_8(&self, data: &[u8]) -> Result<Vec<[u8; 4]>, &'static str> {
+        if self.depth == 16 {
+            return Err("16 bit samples don't fit in 8 bits");
+        }
+        let mut colors = Vec::new();
+        for i in 0..data.len() * 8 / self.data_len() {
+            let mut raw = [0u8; 4];
+            for (c, channel) in raw[..self.channels() as usize].iter_mut().enumerate() {
+                *channel = self.sample_8(data, i, c);
+            }
+            match self.kind {
+                ColorKind::Grey(false) => colors.push([raw[0], raw[0], raw[0], u8::MAX]),
+                ColorKind::Grey(true) => colors.push([raw[0], raw[0], raw[0], raw[1]]),
+                ColorKind::True(false) => colors.push([raw[0], raw[1], raw[2], u8::MAX]),
+                ColorKind::True(true) => colors.push(raw),
+                ColorKind::Indexed => return Err("Indexed color is not supported yet"),
+            }
+        }
+        Ok(colors)
+    }
+
+    /// Like [`PngColor::parse`], but converts each pixel straight to a 16 bit
+    /// luminance value, ignoring alpha
+    pub fn parse_luma(&self, data: &[u8]) -> Result<Vec<u16>, &'static str> {
+        let pixels = 0..data.len() * 8 / self.data_len();
+        let luma = match self.kind {
+            ColorKind::Grey(_) => pixels.map(|i| self.sample(data, i, 0)).collect(),
+            ColorKind::True(_) => pixels
+                .map(|i| {
+                    Color::new_opaque(
+                        self.sample(data, i, 0),
+                        self.sample(data, i, 1),
+                        self.sample(data, i, 2),
+                    )
+                    .luma()
+                })
+                .collect(),
+            ColorKind::Indexed => return Err("Indexed color is not supported yet"),
+        };
+        Ok(luma)
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColorKind {
     /// Greyscale (with alpha)
     Grey(bool),
@@ -120,6 +226,18 @@ impl TryFrom<u8> for ColorKind {
     }
 }
 
+impl From<ColorKind> for u8 {
+    fn from(value: ColorKind) -> Self {
+        match value {
+            ColorKind::Grey(false) => 0,
+            ColorKind::True(false) => 2,
+            ColorKind::Indexed => 3,
+            ColorKind::Grey(true) => 4,
+            ColorKind::True(true) => 6,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,7 +298,7 @@ mod tests {
         let data = [u8::MAX, u8::MAX, 0, u8::MAX, u8::MAX, 0, 0, 0];
         let mut tw = W;
         tw.3 = 0;
-        let mut tb = W;
+        let mut tb = B;
         tb.3 = 0;
 
         let colors = color.parse(&data).unwrap();
@@ -191,4 +309,60 @@ mod tests {
 
         assert_eq!(&colors, &expected);
     }
+
+    #[test]
+    fn test_scale_tables() {
+        assert_eq!(SCALE_1, [0, u16::MAX]);
+        assert_eq!(SCALE_2, [0, 0x5555, 0xAAAA, u16::MAX]);
+        assert_eq!(SCALE_4[0x7], 0x7777);
+        assert!((0..256).all(|i| SCALE_8[i] == i as u16 * 257));
+
+        assert_eq!(SCALE_1_TO_8, [0, u8::MAX]);
+        assert_eq!(SCALE_2_TO_8, [0, 0x55, 0xAA, u8::MAX]);
+        assert_eq!(SCALE_4_TO_8[0x7], 0x77);
+    }
+
+    #[test]
+    fn test_parse_8() {
+        for (kind, depth, data) in [
+            (ColorKind::Grey(false), 2, &[0b10011100u8][..]),
+            (ColorKind::Grey(false), 4, &[0x7f, 0x30][..]),
+            (ColorKind::Grey(true), 8, &[0x12, 0x34, 0x56, 0x78][..]),
+            (ColorKind::True(true), 8, &[1, 2, 3, 4][..]),
+        ] {
+            let color = PngColor::new(kind, depth).unwrap();
+            let wide = color.parse(data).unwrap();
+            let narrow = color.parse_8(data).unwrap();
+            let widen = |[r, g, b, a]: [u8; 4]| {
+                Color::new(
+                    r as u16 * 257,
+                    g as u16 * 257,
+                    b as u16 * 257,
+                    a as u16 * 257,
+                )
+            };
+            assert_eq!(narrow.into_iter().map(widen).collect::<Vec<_>>(), wide);
+        }
+
+        let color = PngColor::new(ColorKind::Grey(false), 16).unwrap();
+        assert!(color.parse_8(&[0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_indexed_unsupported() {
+        let color = PngColor::new(ColorKind::Indexed, 8).unwrap();
+        assert!(color.parse(&[0]).is_err());
+        assert!(color.parse_luma(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_luma() {
+        let color = PngColor::new(ColorKind::True(false), 8).unwrap();
+        let data = [255, 0, 0, 255, 255, 255, 0, 0, 0];
+        assert_eq!(color.parse_luma(&data).unwrap(), [13932, u16::MAX, 0]);
+
+        let color = PngColor::new(ColorKind::Grey(true), 8).unwrap();
+        let data = [0x12, 0, 0x34, u8::MAX];
+        assert_eq!(color.parse_luma(&data).unwrap(), [0x1212, 0x3434]);
+    }
 }
@@ -0,0 +1,80 @@
+/// Lookup table for the CRC-32 used by png chunks
+const CRC_TABLE: [u32; 256] = make_crc_table();
+
+/// Computes the CRC-32 of `bytes`, as used for png chunks (over the chunk
+/// kind and data).
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// Incremental CRC-32, for data that arrives in pieces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { state: u32::MAX }
+    }
+
+    /// Adds `bytes` to the data the CRC is computed over
+    pub fn update(&mut self, bytes: &[u8]) {
+        // based off of https://www.w3.org/TR/png-3/#D-CRCAppendix
+        for &b in bytes {
+            let lookup_ind = (self.state ^ b as u32) as usize & 0xff;
+            self.state = CRC_TABLE[lookup_ind] ^ (self.state >> 8);
+        }
+    }
+
+    /// The CRC of all data so far. More data can still be added after
+    pub const fn finish(&self) -> u32 {
+        self.state ^ u32::MAX
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const fn make_crc_table() -> [u32; 256] {
+    // based off of https://www.w3.org/TR/png-3/#D-CRCAppendix
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            if c & 1 == 1 {
+                c = 0xedb88320u32 ^ (c >> 1);
+            } else {
+                c = c >> 1;
+            }
+            k += 1;
+        }
+        table[n] = c;
+        n += 1
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+}
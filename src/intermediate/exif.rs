@@ -0,0 +1,345 @@
+// Tags in the main image file directory
+const MAKE: u16 = 0x010f;
+const MODEL: u16 = 0x0110;
+const EXIF_IFD: u16 = 0x8769;
+const GPS_IFD: u16 = 0x8825;
+// Tags in the EXIF directory
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+// Tags in the GPS directory
+const GPS_LATITUDE_REF: u16 = 1;
+const GPS_LATITUDE: u16 = 2;
+const GPS_LONGITUDE_REF: u16 = 3;
+const GPS_LONGITUDE: u16 = 4;
+
+// Field types
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+
+/// Date and time as stored in EXIF, in the camera's local time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExifDateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl std::str::FromStr for ExifDateTime {
+    type Err = &'static str;
+
+    /// Parses the EXIF `YYYY:MM:DD HH:MM:SS` format
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let b = s.as_bytes();
+        if b.len() != 19 || b[4] != b':' || b[7] != b':' || b[10] != b' ' {
+            return Err("Invalid EXIF date");
+        }
+        if b[13] != b':' || b[16] != b':' {
+            return Err("Invalid EXIF time");
+        }
+        let num = |range: std::ops::Range<usize>| {
+            s[range]
+                .parse::<u16>()
+                .map_err(|_| "Invalid EXIF date/time number")
+        };
+
+        Ok(Self {
+            year: num(0..4)?,
+            month: num(5..7)? as u8,
+            day: num(8..10)? as u8,
+            hour: num(11..13)? as u8,
+            minute: num(14..16)? as u8,
+            second: num(17..19)? as u8,
+        })
+    }
+}
+
+/// Position in signed decimal degrees. North and east are positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsCoordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Commonly used tags from the data of an eXIf chunk. Tags that are missing
+/// or malformed are left as `None` rather than failing the whole parse.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Exif {
+    make: Option<String>,
+    model: Option<String>,
+    date_time_original: Option<ExifDateTime>,
+    gps: Option<GpsCoordinates>,
+}
+
+impl Exif {
+    /// Camera manufacturer
+    pub fn make(&self) -> Option<&str> {
+        self.make.as_deref()
+    }
+
+    /// Camera model
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// When the photo was taken
+    pub fn date_time_original(&self) -> Option<ExifDateTime> {
+        self.date_time_original
+    }
+
+    /// Where the photo was taken
+    pub fn gps(&self) -> Option<GpsCoordinates> {
+        self.gps
+    }
+}
+
+impl TryFrom<&[u8]> for Exif {
+    type Error = &'static str;
+
+    /// Parses eXIf chunk data, which is a TIFF header followed by image file
+    /// directories. See https://www.w3.org/TR/png-3/#eXIf
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let tiff = Tiff::new(value).ok_or("Invalid EXIF header")?;
+        let ifd0 = tiff
+            .u32(4)
+            .and_then(|o| tiff.entries(o))
+            .ok_or("Invalid EXIF image file directory")?;
+        let find = |entries: &[Entry], tag| entries.iter().find(|e| e.tag == tag).copied();
+
+        let mut exif = Exif {
+            make: find(&ifd0, MAKE).and_then(|e| tiff.ascii(e)),
+            model: find(&ifd0, MODEL).and_then(|e| tiff.ascii(e)),
+            ..Default::default()
+        };
+
+        let sub_ifd = |tag| {
+            find(&ifd0, tag)
+                .and_then(|e| tiff.long(e))
+                .and_then(|o| tiff.entries(o))
+        };
+        if let Some(entries) = sub_ifd(EXIF_IFD) {
+            exif.date_time_original = find(&entries, DATE_TIME_ORIGINAL)
+                .and_then(|e| tiff.ascii(e))
+                .and_then(|s| s.parse().ok());
+        }
+        if let Some(entries) = sub_ifd(GPS_IFD) {
+            let coordinate = |ref_tag, tag, negative| {
+                let degrees = find(&entries, tag)
+                    .and_then(|e| tiff.rationals(e))
+                    .and_then(|r| Some(*r.first()? + r.get(1)? / 60.0 + r.get(2)? / 3600.0))?;
+                let reference = find(&entries, ref_tag).and_then(|e| tiff.ascii(e))?;
+                Some(if reference == negative {
+                    -degrees
+                } else {
+                    degrees
+                })
+            };
+            exif.gps = coordinate(GPS_LATITUDE_REF, GPS_LATITUDE, "S")
+                .zip(coordinate(GPS_LONGITUDE_REF, GPS_LONGITUDE, "W"))
+                .map(|(latitude, longitude)| GpsCoordinates {
+                    latitude,
+                    longitude,
+                });
+        }
+
+        Ok(exif)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// Offset of the value itself, whether inline or not
+    value: usize,
+}
+
+/// Bounds checked reads from TIFF data in either byte order
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let tiff = Self { data, big_endian };
+        (tiff.u16(2)? == 42).then_some(tiff)
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.data.get(offset..)?.first_chunk::<N>().copied()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.bytes(offset)?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.bytes(offset)?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    /// Entries of the directory at `offset`, skipping any that can't be read
+    fn entries(&self, offset: u32) -> Option<Vec<Entry>> {
+        let offset = offset as usize;
+        let count = self.u16(offset)? as usize;
+        let entries = (0..count)
+            .filter_map(|i| {
+                let start = offset + 2 + i * 12;
+                let kind = self.u16(start + 2)?;
+                let count = self.u32(start + 4)?;
+                let size = match kind {
+                    SHORT => 2,
+                    LONG => 4,
+                    RATIONAL => 8,
+                    _ => 1,
+                } * count as usize;
+                let value = if size <= 4 {
+                    start + 8
+                } else {
+                    self.u32(start + 8)? as usize
+                };
+                Some(Entry {
+                    tag: self.u16(start)?,
+                    kind,
+                    count,
+                    value,
+                })
+            })
+            .collect();
+        Some(entries)
+    }
+
+    fn ascii(&self, entry: Entry) -> Option<String> {
+        if entry.kind != ASCII {
+            return None;
+        }
+        let bytes = self
+            .data
+            .get(entry.value..entry.value.checked_add(entry.count as usize)?)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8(bytes[..end].to_vec()).ok()
+    }
+
+    fn long(&self, entry: Entry) -> Option<u32> {
+        match entry.kind {
+            SHORT => self.u16(entry.value).map(u32::from),
+            LONG => self.u32(entry.value),
+            _ => None,
+        }
+    }
+
+    fn rationals(&self, entry: Entry) -> Option<Vec<f64>> {
+        if entry.kind != RATIONAL {
+            return None;
+        }
+        (0..entry.count as usize)
+            .map(|i| {
+                let numerator = self.u32(entry.value + i * 8)?;
+                let denominator = self.u32(entry.value + i * 8 + 4)?;
+                (denominator != 0).then(|| numerator as f64 / denominator as f64)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXIF_BIG_ENDIAN: &[u8] = &[
+        0x4d, 0x4d, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x01, 0x0f, 0x00, 0x02, 0x00,
+        0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x3e, 0x01, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00, 0x07,
+        0x00, 0x00, 0x00, 0x44, 0x87, 0x69, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x4b, 0x88, 0x25, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x71, 0x00, 0x00,
+        0x00, 0x00, 0x43, 0x61, 0x6e, 0x6f, 0x6e, 0x00, 0x45, 0x4f, 0x53, 0x20, 0x35, 0x44, 0x00,
+        0x00, 0x01, 0x90, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x5d, 0x00,
+        0x00, 0x00, 0x00, 0x32, 0x30, 0x32, 0x34, 0x3a, 0x30, 0x35, 0x3a, 0x31, 0x37, 0x20, 0x31,
+        0x33, 0x3a, 0x34, 0x35, 0x3a, 0x30, 0x39, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00, 0x02, 0x00,
+        0x00, 0x00, 0x02, 0x4e, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0xa7, 0x00, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x57, 0x00, 0x00,
+        0x00, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0xbf, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x1e, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0f,
+        0x78, 0x00, 0x00, 0x00, 0x64,
+    ];
+
+    const EXIF_LITTLE_ENDIAN: &[u8] = &[
+        0x49, 0x49, 0x2a, 0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0x00, 0x0f, 0x01, 0x02, 0x00, 0x06,
+        0x00, 0x00, 0x00, 0x3e, 0x00, 0x00, 0x00, 0x10, 0x01, 0x02, 0x00, 0x07, 0x00, 0x00, 0x00,
+        0x44, 0x00, 0x00, 0x00, 0x69, 0x87, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x4b, 0x00, 0x00,
+        0x00, 0x25, 0x88, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x71, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x43, 0x61, 0x6e, 0x6f, 0x6e, 0x00, 0x45, 0x4f, 0x53, 0x20, 0x35, 0x44, 0x00,
+        0x01, 0x00, 0x03, 0x90, 0x02, 0x00, 0x14, 0x00, 0x00, 0x00, 0x5d, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x32, 0x30, 0x32, 0x34, 0x3a, 0x30, 0x35, 0x3a, 0x31, 0x37, 0x20, 0x31,
+        0x33, 0x3a, 0x34, 0x35, 0x3a, 0x30, 0x39, 0x00, 0x04, 0x00, 0x01, 0x00, 0x02, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x4e, 0x00, 0x00, 0x00, 0x02, 0x00, 0x05, 0x00, 0x03, 0x00, 0x00, 0x00,
+        0xa7, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00, 0x57, 0x00, 0x00,
+        0x00, 0x04, 0x00, 0x05, 0x00, 0x03, 0x00, 0x00, 0x00, 0xbf, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x33, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x10, 0x0e, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x78, 0x0f, 0x00,
+        0x00, 0x64, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_exif() {
+        for data in [EXIF_BIG_ENDIAN, EXIF_LITTLE_ENDIAN] {
+            let exif = Exif::try_from(data).unwrap();
+            assert_eq!(exif.make(), Some("Canon"));
+            assert_eq!(exif.model(), Some("EOS 5D"));
+            assert_eq!(
+                exif.date_time_original(),
+                Some(ExifDateTime {
+                    year: 2024,
+                    month: 5,
+                    day: 17,
+                    hour: 13,
+                    minute: 45,
+                    second: 9
+                })
+            );
+
+            let gps = exif.gps().unwrap();
+            assert!((gps.latitude - 51.51).abs() < 1e-9);
+            assert!((gps.longitude + 7.0 / 60.0 + 39.6 / 3600.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_truncated_exif() {
+        assert!(Exif::try_from(&EXIF_BIG_ENDIAN[..4]).is_err());
+        assert!(Exif::try_from(&b"XX\0\x2a\0\0\0\x08"[..]).is_err());
+
+        // Tags pointing past the end are skipped rather than failing
+        let exif = Exif::try_from(&EXIF_BIG_ENDIAN[..100]).unwrap();
+        assert_eq!(exif.make(), Some("Canon"));
+        assert_eq!(exif.gps(), None);
+
+        // A directory claiming more entries than fit keeps the ones that do
+        let mut overlong = EXIF_BIG_ENDIAN.to_vec();
+        overlong[8..10].copy_from_slice(&[0xff, 0xff]);
+        let exif = Exif::try_from(&overlong[..]).unwrap();
+        assert_eq!(exif.make(), Some("Canon"));
+        assert_eq!(exif.model(), Some("EOS 5D"));
+    }
+}
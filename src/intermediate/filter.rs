@@ -1,22 +1,122 @@
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-pub enum Filter {
-    /// Filter method 0. See https://www.w3.org/TR/png-3/#9Filter-types
-    /// Currently the only supported filter method
-    #[default]
-    Zero,
+/// Reverses the filtering of scanlines for a filter method. The filter method
+/// is given in the image header, and decides what the filter type byte at the
+/// start of each scanline means. See https://www.w3.org/TR/png-3/#9Filters
+pub trait Filter: Send + Sync {
+    /// Reconstructs `line` in place. `line` is the scanline without its filter
+    /// type byte, `prev` is the already reconstructed scanline above it (all
+    /// zeros for the first scanline of a pass), and `bpp` is the number of
+    /// bytes per complete pixel, rounded up to 1.
+    fn unfilter(
+        &self,
+        filter_type: u8,
+        line: &mut [u8],
+        prev: &[u8],
+        bpp: usize,
+    ) -> Result<(), &'static str>;
 }
 
-impl TryFrom<u8> for Filter {
-    type Error = &'static str;
+/// Finds the filter for the filter method in the image header
+pub fn from_method(method: u8) -> Result<Box<dyn Filter>, &'static str> {
+    match method {
+        0 => Ok(Box::new(Adaptive)),
+        _ => Err("Unknown filter method"),
+    }
+}
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(Self::Zero),
-            _ => Err("Unknown filter method"),
+/// Filter method 0. See https://www.w3.org/TR/png-3/#9Filter-types
+/// Currently the only standard filter method
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Adaptive;
+
+impl Filter for Adaptive {
+    fn unfilter(
+        &self,
+        filter_type: u8,
+        line: &mut [u8],
+        prev: &[u8],
+        bpp: usize,
+    ) -> Result<(), &'static str> {
+        if prev.len() != line.len() {
+            return Err("Previous scanline has a different length");
+        }
+
+        // `a` is the byte `bpp` to the left, `b` the byte above and `c` the
+        // byte above `a`. Bytes left of the scanline count as 0
+        match FilterKind::try_from(filter_type)? {
+            FilterKind::None => (),
+            FilterKind::Sub => {
+                for i in bpp..line.len() {
+                    line[i] = line[i].wrapping_add(line[i - bpp]);
+                }
+            }
+            FilterKind::Up => {
+                for (x, b) in line.iter_mut().zip(prev) {
+                    *x = x.wrapping_add(*b);
+                }
+            }
+            FilterKind::Average => {
+                for i in 0..line.len() {
+                    let a = if i >= bpp { line[i - bpp] } else { 0 };
+                    let average = (a as u16 + prev[i] as u16) / 2;
+                    line[i] = line[i].wrapping_add(average as u8);
+                }
+            }
+            FilterKind::Paeth => {
+                for i in 0..line.len() {
+                    let (a, c) = if i >= bpp {
+                        (line[i - bpp], prev[i - bpp])
+                    } else {
+                        (0, 0)
+                    };
+                    line[i] = line[i].wrapping_add(paeth(a, prev[i], c));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Adaptive {
+    /// Applies filter type `kind` to `line`, the reverse of
+    /// [`Filter::unfilter`], replacing the contents of `out`. `prev` is the
+    /// unfiltered scanline above.
+    pub fn filter(kind: FilterKind, line: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+        out.clear();
+        for (i, (&x, &b)) in line.iter().zip(prev).enumerate() {
+            let (a, c) = if i >= bpp {
+                (line[i - bpp], prev[i - bpp])
+            } else {
+                (0, 0)
+            };
+            let predicted = match kind {
+                FilterKind::None => 0,
+                FilterKind::Sub => a,
+                FilterKind::Up => b,
+                FilterKind::Average => ((a as u16 + b as u16) / 2) as u8,
+                FilterKind::Paeth => paeth(a, b, c),
+            };
+            out.push(x.wrapping_sub(predicted));
         }
     }
 }
 
+/// Whichever of `a` (left), `b` (above) and `c` (upper left) is closest to
+/// `a + b - c`, preferring them in that order on ties
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Filter types for filter method 0
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum FilterKind {
     #[default]
@@ -27,6 +127,10 @@ pub enum FilterKind {
     Paeth,
 }
 
+impl FilterKind {
+    pub const ALL: [Self; 5] = [Self::None, Self::Sub, Self::Up, Self::Average, Self::Paeth];
+}
+
 impl TryFrom<u8> for FilterKind {
     type Error = &'static str;
 
@@ -41,3 +145,62 @@ impl TryFrom<u8> for FilterKind {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unfilter(kind: FilterKind, line: &[u8], prev: &[u8], bpp: usize) -> Vec<u8> {
+        let mut line = line.to_vec();
+        Adaptive.unfilter(kind as u8, &mut line, prev, bpp).unwrap();
+        line
+    }
+
+    #[test]
+    fn test_sub() {
+        let line = unfilter(FilterKind::Sub, &[10, 20, 5, 5, 250, 10], &[0; 6], 2);
+        assert_eq!(line, [10, 20, 15, 25, 9, 35]);
+    }
+
+    #[test]
+    fn test_up() {
+        let line = unfilter(FilterKind::Up, &[1, 2, 200], &[10, 20, 100], 1);
+        assert_eq!(line, [11, 22, 44]);
+    }
+
+    #[test]
+    fn test_average() {
+        // (0 + 9) / 2 = 4, then (14 + 3) / 2 = 8
+        let line = unfilter(FilterKind::Average, &[10, 1], &[9, 3], 1);
+        assert_eq!(line, [14, 9]);
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(0, 0, 0), 0);
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 20, 15), 15);
+
+        // First byte predicted from above only, second from paeth(11, 5, 10) = 5
+        let line = unfilter(FilterKind::Paeth, &[1, 2], &[10, 5], 1);
+        assert_eq!(line, [11, 7]);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let line = [10, 20, 15, 25, 9, 35];
+        let prev = [3, 250, 0, 17, 40, 1];
+        let mut filtered = Vec::new();
+        for kind in FilterKind::ALL {
+            Adaptive::filter(kind, &line, &prev, 2, &mut filtered);
+            assert_eq!(unfilter(kind, &filtered, &prev, 2), line, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_unknown_filter_type() {
+        let mut line = [0; 2];
+        assert!(Adaptive.unfilter(5, &mut line, &[0; 2], 1).is_err());
+    }
+}
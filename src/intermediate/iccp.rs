@@ -0,0 +1,82 @@
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use super::{chunk_kind, keyword_bytes, Chunk, MAX_CHUNK_LENGTH};
+
+/// Length of the fixed ICC profile header
+const ICC_HEADER_LEN: usize = 128;
+
+/// Builds an iCCP chunk embedding `profile` under the profile name `name`.
+/// The profile is zlib compressed, as required by the standard.
+///
+/// Fails if the name isn't a valid keyword, the profile doesn't have a valid
+/// ICC header, or the compressed profile is too large to fit in a chunk.
+pub fn iccp_chunk(name: &str, profile: &[u8]) -> Result<Chunk, &'static str> {
+    let mut data = keyword_bytes(name)?;
+
+    if profile.len() < ICC_HEADER_LEN || &profile[36..40] != b"acsp" {
+        return Err("ICC profile is missing its header");
+    }
+    let declared_len = u32::from_be_bytes(*profile.first_chunk::<4>().expect("128 > 4"));
+    if declared_len as usize != profile.len() {
+        return Err("ICC profile length doesn't match its header");
+    }
+
+    data.push(0); // Null separator
+    data.push(0); // Compression method: zlib
+
+    let mut encoder = ZlibEncoder::new(data, Compression::default());
+    encoder
+        .write_all(profile)
+        .map_err(|_| "Failed to compress ICC profile")?;
+    let data = encoder
+        .finish()
+        .map_err(|_| "Failed to compress ICC profile")?;
+    if data.len() > MAX_CHUNK_LENGTH as usize {
+        return Err("Compressed ICC profile is too large for a chunk");
+    }
+
+    Ok(Chunk::new(chunk_kind::ICCP, data.into_boxed_slice()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+
+    fn profile() -> Vec<u8> {
+        let mut profile = vec![0u8; 132];
+        profile[..4].copy_from_slice(&132u32.to_be_bytes());
+        profile[36..40].copy_from_slice(b"acsp");
+        profile
+    }
+
+    #[test]
+    fn test_iccp_chunk() {
+        let profile = profile();
+        let chunk = iccp_chunk("Working RGB", &profile).unwrap();
+        assert_eq!(chunk.kind(), chunk_kind::ICCP);
+
+        let (name, rest) = chunk.data().split_at(11);
+        assert_eq!(name, b"Working RGB");
+        assert_eq!(rest[..2], [0, 0]);
+
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(&rest[2..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, profile);
+    }
+
+    #[test]
+    fn test_invalid_iccp() {
+        let profile = profile();
+        assert!(iccp_chunk("", &profile).is_err());
+        assert!(iccp_chunk("Working RGB", &profile[..128]).is_err());
+        assert!(iccp_chunk("Working RGB", &[0; 16]).is_err());
+    }
+}
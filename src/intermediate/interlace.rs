@@ -0,0 +1,108 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Interlace {
+    /// No interlacing. Scanlines are stored top to bottom
+    #[default]
+    None,
+    /// Adam7 interlacing. See https://www.w3.org/TR/png-3/#8Interlace
+    Adam7,
+}
+
+impl TryFrom<u8> for Interlace {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Adam7),
+            _ => Err("Unknown interlace method"),
+        }
+    }
+}
+
+/// The pixels of the full image making up one Adam7 pass: every `dx`th
+/// column starting at `x`, of every `dy`th row starting at `y`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub x: u32,
+    pub y: u32,
+    pub dx: u32,
+    pub dy: u32,
+}
+
+impl Pass {
+    /// Width and height of the reduced image for this pass. Either may be
+    /// 0 for small images, in which case the pass has no scanlines.
+    pub const fn size(self, width: u32, height: u32) -> (u32, u32) {
+        (
+            width.saturating_sub(self.x).div_ceil(self.dx),
+            height.saturating_sub(self.y).div_ceil(self.dy),
+        )
+    }
+}
+
+/// The seven Adam7 passes, in the order they're stored
+pub const ADAM7_PASSES: [Pass; 7] = [
+    Pass {
+        x: 0,
+        y: 0,
+        dx: 8,
+        dy: 8,
+    },
+    Pass {
+        x: 4,
+        y: 0,
+        dx: 8,
+        dy: 8,
+    },
+    Pass {
+        x: 0,
+        y: 4,
+        dx: 4,
+        dy: 8,
+    },
+    Pass {
+        x: 2,
+        y: 0,
+        dx: 4,
+        dy: 4,
+    },
+    Pass {
+        x: 0,
+        y: 2,
+        dx: 2,
+        dy: 4,
+    },
+    Pass {
+        x: 1,
+        y: 0,
+        dx: 2,
+        dy: 2,
+    },
+    Pass {
+        x: 0,
+        y: 1,
+        dx: 1,
+        dy: 2,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_sizes() {
+        let sizes = ADAM7_PASSES.map(|pass| pass.size(2, 2));
+        assert_eq!(
+            sizes,
+            [(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (1, 1), (2, 1)]
+        );
+
+        let pixels: u32 = ADAM7_PASSES
+            .iter()
+            .map(|pass| pass.size(13, 7))
+            .map(|(w, h)| w * h)
+            .sum();
+        assert_eq!(pixels, 13 * 7);
+    }
+}
@@ -0,0 +1,43 @@
+/// Longest keyword allowed in a chunk, in bytes
+pub const MAX_KEYWORD_LENGTH: usize = 79;
+
+/// Checks that `keyword` is a valid chunk keyword (as used by tEXt, zTXt,
+/// iTXt, iCCP and sPLT) and returns its Latin-1 encoding.
+///
+/// Keywords are 1-79 printable Latin-1 characters with no leading, trailing
+/// or consecutive spaces. See https://www.w3.org/TR/png-3/#11keywords
+pub fn keyword_bytes(keyword: &str) -> Result<Vec<u8>, &'static str> {
+    let bytes = keyword
+        .chars()
+        .map(|c| match c as u32 {
+            32..=126 | 161..=255 => Ok(c as u8),
+            _ => Err("Keyword contains a non-printable or non-Latin-1 character"),
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    if bytes.is_empty() || bytes.len() > MAX_KEYWORD_LENGTH {
+        return Err("Keyword must be 1-79 bytes long");
+    }
+    if bytes.starts_with(b" ") || bytes.ends_with(b" ") || keyword.contains("  ") {
+        return Err("Keyword has leading, trailing or consecutive spaces");
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_bytes() {
+        assert_eq!(keyword_bytes("Title").unwrap(), b"Title");
+        assert_eq!(keyword_bytes("caf\u{e9}").unwrap(), b"caf\xe9");
+        assert!(keyword_bytes("").is_err());
+        assert!(keyword_bytes(" Title").is_err());
+        assert!(keyword_bytes("Title ").is_err());
+        assert!(keyword_bytes("Ti  tle").is_err());
+        assert!(keyword_bytes("Ti\ttle").is_err());
+        assert!(keyword_bytes("\u{2603}").is_err());
+        assert!(keyword_bytes(&"a".repeat(80)).is_err());
+    }
+}
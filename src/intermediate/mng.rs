@@ -0,0 +1,182 @@
+use std::io::{self, ErrorKind, Read};
+
+use super::{
+    chunk_kind::{self, JHDR, MEND, MHDR},
+    Chunk, PNG_SIG,
+};
+
+pub const MNG_SIG: [u8; 8] = [138, 77, 78, 71, 13, 10, 26, 10];
+pub const JNG_SIG: [u8; 8] = [139, 74, 78, 71, 13, 10, 26, 10];
+
+/// Kind of datastream, as given by its signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Png,
+    /// Multiple-image Network Graphics
+    Mng,
+    /// JPEG Network Graphics
+    Jng,
+}
+
+impl Container {
+    /// Recognizes the 8 byte signature at the start of a datastream
+    pub fn detect(signature: &[u8; 8]) -> Option<Self> {
+        match *signature {
+            PNG_SIG => Some(Self::Png),
+            MNG_SIG => Some(Self::Mng),
+            JNG_SIG => Some(Self::Jng),
+            _ => None,
+        }
+    }
+}
+
+/// Standalone datastream embedded in an MNG, including its signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Embedded {
+    /// IHDR to IEND, readable with [`PngParser`](crate::parser::PngParser)
+    Png(Vec<u8>),
+    /// JHDR to IEND
+    Jng(Vec<u8>),
+}
+
+/// Iterates over the PNG and JNG images embedded in an MNG datastream.
+///
+/// Only enough of MNG is understood to pull the images out. Animation,
+/// delta images (DHDR) and global palettes are ignored, so images relying on
+/// a global PLTE won't decode on their own.
+#[derive(Debug)]
+pub struct MngReader<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> MngReader<R> {
+    /// Checks the MNG signature and header
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut sig = [0u8; 8];
+        reader.read_exact(&mut sig)?;
+        if sig != MNG_SIG {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "MNG missing signature",
+            ));
+        }
+
+        let header = Chunk::read(&mut reader)?;
+        header.verify()?;
+        if header.kind() != MHDR {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "MNG didn't start with expected header",
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            done: false,
+        })
+    }
+
+    fn next_embedded(&mut self) -> io::Result<Option<Embedded>> {
+        loop {
+            let chunk = Chunk::read(&mut self.reader)?;
+            chunk.verify()?;
+            let (mut data, embedded): (_, fn(Vec<u8>) -> Embedded) = match chunk.kind() {
+                MEND => return Ok(None),
+                chunk_kind::IHDR => (PNG_SIG.to_vec(), Embedded::Png),
+                JHDR => (JNG_SIG.to_vec(), Embedded::Jng),
+                _ => continue,
+            };
+
+            chunk.write(&mut data)?;
+            loop {
+                let chunk = Chunk::read(&mut self.reader)?;
+                chunk.verify()?;
+                chunk.write(&mut data)?;
+                if chunk.kind() == chunk_kind::IEND {
+                    return Ok(Some(embedded(data)));
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for MngReader<R> {
+    type Item = io::Result<Embedded>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.next_embedded().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TINY_PNG;
+
+    fn tiny_mng(images: usize) -> Vec<u8> {
+        let mut mng = MNG_SIG.to_vec();
+        Chunk::new(MHDR, vec![0; 28].into())
+            .write(&mut mng)
+            .unwrap();
+        for _ in 0..images {
+            mng.extend_from_slice(&TINY_PNG[8..]);
+        }
+        Chunk::new(MEND, Box::new([])).write(&mut mng).unwrap();
+        mng
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Container::detect(TINY_PNG.first_chunk().unwrap()),
+            Some(Container::Png)
+        );
+        assert_eq!(Container::detect(&MNG_SIG), Some(Container::Mng));
+        assert_eq!(Container::detect(&JNG_SIG), Some(Container::Jng));
+        assert_eq!(Container::detect(&[0; 8]), None);
+    }
+
+    #[test]
+    fn test_embedded_images() {
+        let mng = tiny_mng(2);
+        let images = MngReader::new(&mng[..])
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            images,
+            [
+                Embedded::Png(TINY_PNG.to_vec()),
+                Embedded::Png(TINY_PNG.to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_not_mng() {
+        assert!(MngReader::new(TINY_PNG).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_chunk() {
+        let mut mng = tiny_mng(1);
+        mng[81] ^= 0xff; // Embedded IDAT data
+        assert!(matches!(
+            MngReader::new(&mng[..]).unwrap().next(),
+            Some(Err(_))
+        ));
+
+        let mut mng = tiny_mng(1);
+        mng[20] ^= 0xff; // MHDR data
+        assert!(MngReader::new(&mng[..]).is_err());
+    }
+}
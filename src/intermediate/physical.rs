@@ -0,0 +1,77 @@
+/// Unit of the pixels per unit in a pHYs chunk
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Only the aspect ratio is known
+    #[default]
+    Unknown,
+    Meter,
+}
+
+/// Intended pixel size or aspect ratio, from a pHYs chunk.
+/// See https://www.w3.org/TR/png-3/#11pHYs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    /// Pixels per unit along the x axis
+    pub x: u32,
+    /// Pixels per unit along the y axis
+    pub y: u32,
+    pub unit: Unit,
+}
+
+impl TryFrom<&[u8]> for PhysicalDimensions {
+    type Error = &'static str;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let data: &[u8; 9] = value.try_into().map_err(|_| "pHYs chunk must be 9 bytes")?;
+        let x = u32::from_be_bytes(*data.first_chunk::<4>().expect("9 > 4"));
+        let y = u32::from_be_bytes(*data[4..].first_chunk::<4>().expect("5 > 4"));
+        let unit = match data[8] {
+            0 => Unit::Unknown,
+            1 => Unit::Meter,
+            _ => return Err("Unknown pHYs unit"),
+        };
+        if x == 0 || y == 0 {
+            return Err("pHYs pixels per unit must be non-zero");
+        }
+
+        Ok(Self { x, y, unit })
+    }
+}
+
+impl PhysicalDimensions {
+    /// Data of a pHYs chunk holding these dimensions
+    pub fn to_bytes(self) -> [u8; 9] {
+        let mut data = [0; 9];
+        data[..4].copy_from_slice(&self.x.to_be_bytes());
+        data[4..8].copy_from_slice(&self.y.to_be_bytes());
+        data[8] = match self.unit {
+            Unit::Unknown => 0,
+            Unit::Meter => 1,
+        };
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = [0, 0, 0x0b, 0x13, 0, 0, 0x16, 0x26, 1];
+        let dims = PhysicalDimensions::try_from(&data[..]).unwrap();
+        assert_eq!(dims.to_bytes(), data);
+        assert_eq!(
+            dims,
+            PhysicalDimensions {
+                x: 2835,
+                y: 5670,
+                unit: Unit::Meter
+            }
+        );
+
+        assert!(PhysicalDimensions::try_from(&data[..8]).is_err());
+        assert!(PhysicalDimensions::try_from(&[0, 0, 0, 1, 0, 0, 0, 1, 2][..]).is_err());
+        assert!(PhysicalDimensions::try_from(&[0, 0, 0, 0, 0, 0, 0, 1, 0][..]).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{self, ErrorKind, Read, Seek, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use super::{chunk_kind, read_signature, Chunk, ChunkKind, MAX_CHUNK_LENGTH};
+use crate::{Color, Png};
+
+/// Longest side of a preview, in pixels
+pub const MAX_PREVIEW_SIZE: u32 = 256;
+
+/// Builds a private prVW chunk holding `preview` as 8 bit RGBA, to be inserted
+/// before the image data. Make the preview with [`PngParser::parse_scaled`]
+/// or similar; it is stored as is.
+///
+/// The chunk is marked unsafe to copy, so editors that change the image drop
+/// the stale preview.
+///
+/// [`PngParser::parse_scaled`]: crate::parser::PngParser::parse_scaled
+pub fn preview_chunk(preview: &Png) -> Result<Chunk, &'static str> {
+    if preview.width() > MAX_PREVIEW_SIZE || preview.height() > MAX_PREVIEW_SIZE {
+        return Err("Preview is too large");
+    }
+
+    let mut data = preview.width().to_be_bytes().to_vec();
+    data.extend_from_slice(&preview.height().to_be_bytes());
+    let mut encoder = ZlibEncoder::new(data, Compression::best());
+    for c in preview.pixels() {
+        let rgba =
+            [c.red(), c.green(), c.blue(), c.alpha()].map(|v| ((v as u32 + 128) / 257) as u8);
+        encoder
+            .write_all(&rgba)
+            .map_err(|_| "Failed to compress preview")?;
+    }
+    let data = encoder.finish().map_err(|_| "Failed to compress preview")?;
+    if data.len() > MAX_CHUNK_LENGTH as usize {
+        return Err("Compressed preview is too large for a chunk");
+    }
+
+    Ok(Chunk::new(chunk_kind::PRVW, data.into_boxed_slice()))
+}
+
+/// Decodes the data of a prVW chunk
+pub fn parse_preview(data: &[u8]) -> Result<Png, &'static str> {
+    let (size, compressed) = data
+        .split_first_chunk::<8>()
+        .ok_or("Preview chunk too short")?;
+    let width = u32::from_be_bytes(*size.first_chunk::<4>().expect("8 > 4"));
+    let height = u32::from_be_bytes(*size[4..].first_chunk::<4>().expect("4 = 4"));
+    if width > MAX_PREVIEW_SIZE || height > MAX_PREVIEW_SIZE {
+        return Err("Preview is too large");
+    }
+
+    let expected = width as usize * height as usize * 4;
+    let mut rgba = Vec::with_capacity(expected);
+    ZlibDecoder::new(compressed)
+        .take(expected as u64 + 1)
+        .read_to_end(&mut rgba)
+        .map_err(|_| "Failed to decompress preview")?;
+    if rgba.len() != expected {
+        return Err("Preview has the wrong number of pixels");
+    }
+
+    let pixels = rgba.chunks_exact(4).map(|p| {
+        Color::new(
+            p[0] as u16 * 257,
+            p[1] as u16 * 257,
+            p[2] as u16 * 257,
+            p[3] as u16 * 257,
+        )
+    });
+    Png::try_new(height, width, pixels.collect())
+}
+
+/// Finds and decodes the preview embedded with [`preview_chunk`], reading
+/// only as far as the image data. Returns `None` if there is no preview.
+pub fn read_preview(mut reader: impl Read + Seek) -> io::Result<Option<Png>> {
+    read_signature(&mut reader)?;
+
+    loop {
+        let mut bound = [0u8; 8];
+        reader.read_exact(&mut bound)?;
+        let len = u32::from_be_bytes(*bound.first_chunk::<4>().expect("8 > 4"));
+        let kind = ChunkKind::try_from(bound[4..].first_chunk::<4>().expect("4 = 4"))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+        match kind {
+            chunk_kind::PRVW => {
+                reader.seek_relative(-8)?;
+                let chunk = Chunk::read(&mut reader)?;
+                chunk.verify()?;
+                return parse_preview(chunk.data())
+                    .map(Some)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+            }
+            chunk_kind::IDAT | chunk_kind::IEND => return Ok(None),
+            _ => reader.seek_relative(len as i64 + 4)?, // Skip data and crc
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::intermediate::rewriter::Rewriter;
+    use crate::test_util::TINY_PNG;
+
+    #[test]
+    fn test_preview() {
+        let preview = Png::new(
+            1,
+            2,
+            vec![
+                Color::new(0x1212, 0x3434, 0x5656, 0xffff),
+                Color::new(0, 0, 0, 0),
+            ],
+        );
+        let chunk = preview_chunk(&preview).unwrap();
+        assert!(!chunk.kind().copy_safe());
+        assert_eq!(parse_preview(chunk.data()).unwrap(), preview);
+
+        // 8 bit colors survive the round trip, others are rounded
+        let rounded = Png::new(1, 2, vec![Color::new(0x12ff, 0x8080, 0, 0xff7f); 2]);
+        assert_eq!(
+            parse_preview(preview_chunk(&rounded).unwrap().data()).unwrap(),
+            Png::new(1, 2, vec![Color::new(0x1313, 0x8080, 0, 0xffff); 2])
+        );
+
+        assert_eq!(read_preview(Cursor::new(TINY_PNG)).unwrap(), None);
+        let mut with_preview = Vec::new();
+        Rewriter::new()
+            .insert_before(chunk_kind::IDAT, chunk)
+            .rewrite(TINY_PNG, &mut with_preview)
+            .unwrap();
+        assert_eq!(
+            read_preview(Cursor::new(with_preview)).unwrap(),
+            Some(preview)
+        );
+    }
+
+    #[test]
+    fn test_invalid_preview() {
+        let large = Png::new(1, 257, vec![Color::new(0, 0, 0, 0); 257]);
+        assert!(preview_chunk(&large).is_err());
+
+        let preview = Png::new(1, 2, vec![Color::new(0, 0, 0, 0); 2]);
+        let chunk = preview_chunk(&preview).unwrap();
+        let mut data = chunk.data().to_vec();
+        data[7] = 3; // Claim three rows
+        assert!(parse_preview(&data).is_err());
+        assert!(parse_preview(&data[..6]).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use super::{chunk_kind, xmp::XMP_KEYWORD, Chunk, ChunkKind, MAX_CHUNK_LENGTH, PNG_SIG};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// Insert a chunk before the first chunk of the given kind
+    InsertBefore(ChunkKind, Chunk),
+    /// Insert a chunk after the first chunk of the given kind
+    InsertAfter(ChunkKind, Chunk),
+    /// Replace the first chunk of the given kind
+    Replace(ChunkKind, Chunk),
+    /// Remove every chunk of the given kind
+    Delete(ChunkKind),
+    /// Remove every chunk in the given metadata categories
+    Strip(StripMetadata),
+}
+
+/// Categories of metadata chunks to remove from a datastream. Chunks that
+/// affect how the pixels are displayed (gAMA, cHRM, sRGB, iCCP, cICP, mDCV,
+/// cLLI, sBIT and tRNS) are never removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StripMetadata {
+    /// tEXt, zTXt and iTXt chunks
+    pub text: bool,
+    /// tIME chunks
+    pub timestamps: bool,
+    /// eXIf chunks
+    pub exif: bool,
+    /// Chunks that can carry a location: eXIf (GPS tags) and XMP packets in
+    /// iTXt
+    pub location: bool,
+    /// Every ancillary chunk, including unknown and private ones
+    pub ancillary: bool,
+}
+
+impl StripMetadata {
+    /// Strips everything that could identify a user: text, timestamps, EXIF
+    /// and location data
+    pub const fn privacy() -> Self {
+        Self {
+            text: true,
+            timestamps: true,
+            exif: true,
+            location: true,
+            ancillary: false,
+        }
+    }
+
+    /// Strips every ancillary chunk that doesn't affect the displayed pixels
+    pub const fn all() -> Self {
+        Self {
+            text: true,
+            timestamps: true,
+            exif: true,
+            location: true,
+            ancillary: true,
+        }
+    }
+
+    /// Whether a chunk of the given kind should be removed. iTXt chunks with
+    /// an XMP packet are also removed with `location`; see
+    /// [`StripMetadata::strips_xmp`].
+    pub fn strips(&self, kind: ChunkKind) -> bool {
+        match kind {
+            chunk_kind::GAMA
+            | chunk_kind::CHRM
+            | chunk_kind::SRGB
+            | chunk_kind::ICCP
+            | chunk_kind::CICP
+            | chunk_kind::MDCV
+            | chunk_kind::CLLI
+            | chunk_kind::SBIT
+            | chunk_kind::TRNS => false,
+            chunk_kind::TEXT | chunk_kind::ZTXT | chunk_kind::ITXT => self.text || self.ancillary,
+            chunk_kind::TIME => self.timestamps || self.ancillary,
+            chunk_kind::EXIF => self.exif || self.location || self.ancillary,
+            k => self.ancillary && !k.critical(),
+        }
+    }
+
+    /// Whether an iTXt chunk holding an XMP packet should be removed
+    pub fn strips_xmp(&self) -> bool {
+        self.strips(chunk_kind::ITXT) || self.location
+    }
+}
+
+/// Copies a PNG datastream chunk by chunk, applying a list of edits.
+///
+/// Every chunk that isn't touched by an edit is copied byte for byte,
+/// including its original crc, even if that crc is wrong. Chunks are not
+/// validated beyond their length and type, so the output only differs from
+/// the input where an edit says it should.
+#[derive(Debug, Default, Clone)]
+pub struct Rewriter {
+    edits: Vec<Edit>,
+}
+
+impl Rewriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an edit. Edits anchored to the same chunk are applied in the order
+    /// they were added.
+    pub fn edit(mut self, edit: Edit) -> Self {
+        self.edits.push(edit);
+        self
+    }
+
+    pub fn insert_before(self, kind: ChunkKind, chunk: Chunk) -> Self {
+        self.edit(Edit::InsertBefore(kind, chunk))
+    }
+
+    pub fn insert_after(self, kind: ChunkKind, chunk: Chunk) -> Self {
+        self.edit(Edit::InsertAfter(kind, chunk))
+    }
+
+    pub fn replace(self, kind: ChunkKind, chunk: Chunk) -> Self {
+        self.edit(Edit::Replace(kind, chunk))
+    }
+
+    pub fn delete(self, kind: ChunkKind) -> Self {
+        self.edit(Edit::Delete(kind))
+    }
+
+    pub fn strip_metadata(self, strip: StripMetadata) -> Self {
+        self.edit(Edit::Strip(strip))
+    }
+
+    /// Copies the metadata chunks of another image (e.g. from
+    /// [`read_chunks`](super::read_chunks)) into the rewritten one, replacing
+    /// any chunks of the same kinds. The chunks are inserted after IHDR.
+    ///
+    /// Only ancillary chunks that stay valid when the image data changes are
+    /// copied: those marked safe to copy, plus the unsafe ones known to
+    /// describe the image rather than its encoding (see
+    /// [`ChunkKind::copy_safe`]). tIME is left out, since the modification
+    /// time belongs to the rewritten image.
+    ///
+    /// iCCP and sRGB must not both be present, so copying either removes the
+    /// other from the rewritten image. If the source has both, iCCP is kept.
+    pub fn transplant_metadata(mut self, source: &[Chunk]) -> Self {
+        let has_iccp = source.iter().any(|c| c.kind() == chunk_kind::ICCP);
+        let chunks = source
+            .iter()
+            .filter(|c| transplantable(c.kind()))
+            .filter(|c| !(has_iccp && c.kind() == chunk_kind::SRGB));
+        for chunk in chunks.clone() {
+            let replaced = match chunk.kind() {
+                chunk_kind::ICCP => [chunk_kind::ICCP, chunk_kind::SRGB].as_slice(),
+                chunk_kind::SRGB => &[chunk_kind::SRGB, chunk_kind::ICCP],
+                _ => &[chunk.kind()],
+            };
+            for kind in replaced {
+                let delete = Edit::Delete(*kind);
+                if !self.edits.contains(&delete) {
+                    self = self.edit(delete);
+                }
+            }
+        }
+        for chunk in chunks {
+            self = self.insert_after(chunk_kind::IHDR, chunk.clone());
+        }
+        self
+    }
+
+    /// Streams the datastream from `reader` to `writer`, applying the edits.
+    /// Anything after the IEND chunk is dropped. Fails if an insert or replace
+    /// edit refers to a chunk kind that never appears.
+    pub fn rewrite(&self, mut reader: impl Read, mut writer: impl Write) -> io::Result<()> {
+        let mut sig = [0u8; 8];
+        reader.read_exact(&mut sig)?;
+        if sig != PNG_SIG {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "PNG missing signature",
+            ));
+        }
+        writer.write_all(&sig)?;
+
+        // Whether each edit has been applied yet. Deletes and strips are never
+        // "done"
+        let mut applied = vec![false; self.edits.len()];
+        loop {
+            let mut header = [0u8; 8];
+            reader.read_exact(&mut header)?;
+            let len = u32::from_be_bytes(*header.first_chunk::<4>().expect("8 > 4"));
+            if len > MAX_CHUNK_LENGTH {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Chunk length too long",
+                ));
+            }
+            let kind = ChunkKind::try_from(header[4..].first_chunk::<4>().expect("4 = 4"))
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+            // The keyword of iTXt chunks is read ahead to check whether they
+            // hold XMP. The rest of the chunk is streamed as usual
+            let mut peeked = Vec::new();
+            if kind == chunk_kind::ITXT {
+                let keyword_len = XMP_KEYWORD.len() as u32 + 1; // With null separator
+                reader
+                    .by_ref()
+                    .take(len.min(keyword_len) as u64)
+                    .read_to_end(&mut peeked)?;
+            }
+            let xmp = peeked.strip_prefix(XMP_KEYWORD.as_bytes()) == Some(b"\0");
+
+            let mut keep = true;
+            let mut after = Vec::new();
+            for (edit, done) in self.edits.iter().zip(applied.iter_mut()) {
+                match edit {
+                    _ if *done => (),
+                    Edit::InsertBefore(k, chunk) if *k == kind => {
+                        chunk.write(&mut writer)?;
+                        *done = true;
+                    }
+                    Edit::InsertAfter(k, chunk) if *k == kind => {
+                        after.push(chunk);
+                        *done = true;
+                    }
+                    Edit::Replace(k, chunk) if *k == kind && keep => {
+                        chunk.write(&mut writer)?;
+                        keep = false;
+                        *done = true;
+                    }
+                    Edit::Delete(k) if *k == kind => keep = false,
+                    Edit::Strip(strip) if strip.strips(kind) => keep = false,
+                    Edit::Strip(strip) if xmp && strip.strips_xmp() => keep = false,
+                    _ => (),
+                }
+            }
+
+            // Data and crc
+            let mut rest = reader.by_ref().take(len as u64 + 4 - peeked.len() as u64);
+            let copied = if keep {
+                writer.write_all(&header)?;
+                writer.write_all(&peeked)?;
+                io::copy(&mut rest, &mut writer)?
+            } else {
+                io::copy(&mut rest, &mut io::sink())?
+            };
+            if peeked.len() as u64 + copied != len as u64 + 4 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+
+            for chunk in after {
+                chunk.write(&mut writer)?;
+            }
+
+            if kind == chunk_kind::IEND {
+                break;
+            }
+        }
+
+        let missing = self
+            .edits
+            .iter()
+            .zip(applied)
+            .any(|(edit, done)| !done && !matches!(edit, Edit::Delete(_) | Edit::Strip(_)));
+        if missing {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "Edited chunk kind not found in datastream",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether a chunk can be carried over to an image with different image data
+fn transplantable(kind: ChunkKind) -> bool {
+    match kind {
+        chunk_kind::GAMA
+        | chunk_kind::CHRM
+        | chunk_kind::SRGB
+        | chunk_kind::ICCP
+        | chunk_kind::CICP
+        | chunk_kind::MDCV
+        | chunk_kind::CLLI => true,
+        k => !k.critical() && k.copy_safe(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{tiny_png_with, TINY_PNG};
+
+    fn text_chunk() -> Chunk {
+        let kind = ChunkKind::try_from(b"tEXt").unwrap();
+        Chunk::new(kind, b"Title\0Tiny".to_vec().into_boxed_slice())
+    }
+
+    #[test]
+    fn test_no_edits() {
+        let mut out = Vec::new();
+        Rewriter::new().rewrite(TINY_PNG, &mut out).unwrap();
+        assert_eq!(out, TINY_PNG);
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let text = text_chunk();
+        let mut out = Vec::new();
+        Rewriter::new()
+            .insert_after(chunk_kind::IHDR, text.clone())
+            .rewrite(TINY_PNG, &mut out)
+            .unwrap();
+
+        let mut expected = TINY_PNG[..33].to_vec();
+        text.write(&mut expected).unwrap();
+        expected.extend_from_slice(&TINY_PNG[33..]);
+        assert_eq!(out, expected);
+
+        let mut restored = Vec::new();
+        Rewriter::new()
+            .delete(text.kind())
+            .rewrite(&out[..], &mut restored)
+            .unwrap();
+        assert_eq!(restored, TINY_PNG);
+    }
+
+    #[test]
+    fn test_preserves_bad_crc() {
+        let mut corrupt = TINY_PNG.to_vec();
+        corrupt[49] ^= 0xff; // IDAT crc
+
+        let mut out = Vec::new();
+        Rewriter::new()
+            .insert_before(chunk_kind::IEND, text_chunk())
+            .rewrite(&corrupt[..], &mut out)
+            .unwrap();
+        assert_eq!(out[..55], corrupt[..55]);
+    }
+
+    #[test]
+    fn test_missing_anchor() {
+        let mut out = Vec::new();
+        let result = Rewriter::new()
+            .replace(ChunkKind::try_from(b"gAMA").unwrap(), text_chunk())
+            .rewrite(TINY_PNG, &mut out);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_strip_metadata() {
+        let time = Chunk::new(chunk_kind::TIME, vec![0x07, 0xea, 1, 1, 0, 0, 0].into());
+        let gamma = Chunk::new(chunk_kind::GAMA, 45455u32.to_be_bytes().into());
+        let mut tagged = Vec::new();
+        Rewriter::new()
+            .insert_after(chunk_kind::IHDR, gamma.clone())
+            .insert_after(chunk_kind::IHDR, text_chunk())
+            .insert_before(chunk_kind::IEND, time)
+            .rewrite(TINY_PNG, &mut tagged)
+            .unwrap();
+
+        let mut out = Vec::new();
+        Rewriter::new()
+            .strip_metadata(StripMetadata::privacy())
+            .rewrite(&tagged[..], &mut out)
+            .unwrap();
+
+        let expected = tiny_png_with([gamma]);
+        assert_eq!(out, expected);
+
+        let mut out = Vec::new();
+        Rewriter::new()
+            .strip_metadata(StripMetadata {
+                timestamps: true,
+                ..Default::default()
+            })
+            .rewrite(&tagged[..], &mut out)
+            .unwrap();
+        assert_eq!(out.len(), tagged.len() - 19);
+    }
+
+    #[test]
+    fn test_strip_location() {
+        let xmp = crate::intermediate::xmp::xmp_chunk("<x:xmpmeta/>").unwrap();
+        let comment = Chunk::new(chunk_kind::ITXT, b"Comment\0\0\0\0\0Hi".to_vec().into());
+        let mut tagged = Vec::new();
+        Rewriter::new()
+            .insert_after(chunk_kind::IHDR, xmp)
+            .insert_before(chunk_kind::IEND, comment.clone())
+            .rewrite(TINY_PNG, &mut tagged)
+            .unwrap();
+
+        // Only the XMP packet can hold a location
+        let mut out = Vec::new();
+        Rewriter::new()
+            .strip_metadata(StripMetadata {
+                location: true,
+                ..Default::default()
+            })
+            .rewrite(&tagged[..], &mut out)
+            .unwrap();
+        let mut expected = Vec::new();
+        Rewriter::new()
+            .insert_before(chunk_kind::IEND, comment)
+            .rewrite(TINY_PNG, &mut expected)
+            .unwrap();
+        assert_eq!(out, expected);
+
+        // Peeked keywords are still copied
+        let mut out = Vec::new();
+        Rewriter::new().rewrite(&tagged[..], &mut out).unwrap();
+        assert_eq!(out, tagged);
+
+        // A huge claimed length fails once the data runs out
+        let mut truncated = TINY_PNG[..33].to_vec();
+        truncated.extend_from_slice(&0x7fff0000u32.to_be_bytes());
+        truncated.extend_from_slice(b"iTXtXML:com.adobe.xmp\0");
+        let result = Rewriter::new()
+            .strip_metadata(StripMetadata::privacy())
+            .rewrite(&truncated[..], &mut Vec::new());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_transplant_metadata() {
+        let gamma = Chunk::new(chunk_kind::GAMA, 45455u32.to_be_bytes().into());
+        let private = Chunk::new(ChunkKind::try_from(b"prIV").unwrap(), Box::new([1]));
+        let mut source = Vec::new();
+        Rewriter::new()
+            .insert_after(chunk_kind::IHDR, gamma.clone())
+            .insert_after(chunk_kind::IHDR, private)
+            .insert_before(chunk_kind::IEND, text_chunk())
+            .rewrite(TINY_PNG, &mut source)
+            .unwrap();
+        let source = crate::intermediate::read_chunks(&source[..]).unwrap();
+
+        let mut out = Vec::new();
+        Rewriter::new()
+            .transplant_metadata(&source)
+            .rewrite(TINY_PNG, &mut out)
+            .unwrap();
+
+        let expected = tiny_png_with([gamma, text_chunk()]);
+        assert_eq!(out, expected);
+
+        // Copying again replaces instead of duplicating
+        let mut again = Vec::new();
+        Rewriter::new()
+            .transplant_metadata(&source)
+            .rewrite(&out[..], &mut again)
+            .unwrap();
+        assert_eq!(again, expected);
+    }
+
+    #[test]
+    fn test_transplant_color_space() {
+        let time = Chunk::new(chunk_kind::TIME, vec![0x07, 0xea, 1, 1, 0, 0, 0].into());
+        let srgb = Chunk::new(chunk_kind::SRGB, Box::new([0]));
+        let iccp = Chunk::new(
+            chunk_kind::ICCP,
+            b"p\0\0\x78\x01\x03\0\0\0\0\x01".to_vec().into(),
+        );
+        let target = tiny_png_with([srgb.clone()]);
+
+        // The profile replaces the target's sRGB, the source's tIME is dropped
+        let mut out = Vec::new();
+        Rewriter::new()
+            .transplant_metadata(&[time, srgb.clone(), iccp.clone()])
+            .rewrite(&target[..], &mut out)
+            .unwrap();
+        let expected = tiny_png_with([iccp.clone()]);
+        assert_eq!(out, expected);
+
+        // And the other way around
+        let mut back = Vec::new();
+        Rewriter::new()
+            .transplant_metadata(&[srgb])
+            .rewrite(&out[..], &mut back)
+            .unwrap();
+        assert_eq!(back, target);
+    }
+}
@@ -0,0 +1,227 @@
+use std::io::{self, ErrorKind, Read};
+
+use super::{chunk::chunk_crc, chunk_kind, Chunk, ChunkKind, MAX_CHUNK_LENGTH};
+use crate::PngError;
+
+/// A chunk borrowed from a png held in memory. Its crc isn't checked until
+/// it's converted to a [`Chunk`] or read as image data.
+#[derive(Debug, Clone, Copy)]
+pub struct SliceChunk<'a> {
+    pub kind: ChunkKind,
+    pub data: &'a [u8],
+    pub crc: u32,
+}
+
+impl SliceChunk<'_> {
+    /// Checks the stored crc against the chunk's kind and data
+    pub fn verify(&self) -> io::Result<()> {
+        if chunk_crc(self.kind, self.data) != self.crc {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Mismatched crc values",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Verifies the crc and copies the chunk
+    pub fn to_chunk(&self) -> io::Result<Chunk> {
+        self.verify()?;
+        Ok(Chunk::with_stored_crc(
+            self.kind,
+            self.data.into(),
+            self.crc,
+        ))
+    }
+}
+
+/// Iterates over the chunks in a slice, starting right after the signature.
+/// Stops after the first error.
+#[derive(Debug, Clone)]
+pub struct SliceChunks<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SliceChunks<'a> {
+    /// `bytes` should start at a chunk, not at the png signature
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// The bytes not read yet, starting at the next chunk
+    pub fn rest(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    fn next_chunk(&mut self) -> io::Result<SliceChunk<'a>> {
+        let eof = || io::Error::new(ErrorKind::UnexpectedEof, "Chunk cut off");
+        let (bound, rest) = self.bytes.split_first_chunk::<8>().ok_or_else(eof)?;
+        let len = u32::from_be_bytes(*bound.first_chunk::<4>().expect("8 > 4"));
+        if len > MAX_CHUNK_LENGTH {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Chunk length too long",
+            ));
+        }
+        let kind = ChunkKind::try_from(bound[4..].first_chunk::<4>().expect("4 = 4"))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+        let (data, rest) = rest.split_at_checked(len as usize).ok_or_else(eof)?;
+        let (crc, rest) = rest.split_first_chunk::<4>().ok_or_else(eof)?;
+        self.bytes = rest;
+        Ok(SliceChunk {
+            kind,
+            data,
+            crc: u32::from_be_bytes(*crc),
+        })
+    }
+}
+
+impl<'a> Iterator for SliceChunks<'a> {
+    type Item = io::Result<SliceChunk<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let chunk = self.next_chunk();
+        if chunk.is_err() {
+            self.bytes = &[];
+        }
+        Some(chunk)
+    }
+}
+
+/// Reads the data of consecutive IDAT chunks straight from a slice, checking
+/// each crc as the chunk is reached. The slice counterpart of
+/// [`ChunkReader`](super::chunk_reader::ChunkReader). Fails with
+/// [`PngError::NonConsecutiveIdat`] if other chunks come between IDAT chunks,
+/// unless made [lenient](Self::lenient).
+#[derive(Debug, Clone)]
+pub struct IdatReader<'a> {
+    chunks: SliceChunks<'a>,
+    current: &'a [u8],
+    /// Skip chunks between IDAT chunks instead of failing
+    lenient: bool,
+    /// A chunk other than IDAT was passed since the last IDAT
+    skipped: bool,
+}
+
+impl<'a> IdatReader<'a> {
+    /// `chunks` should be positioned at the first IDAT chunk
+    pub fn new(chunks: SliceChunks<'a>) -> Self {
+        Self {
+            chunks,
+            current: &[],
+            lenient: false,
+            skipped: false,
+        }
+    }
+
+    /// Allows other chunks between IDAT chunks, which the standard forbids.
+    /// They are skipped. Otherwise reading fails with
+    /// [`PngError::NonConsecutiveIdat`].
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+}
+
+impl Read for IdatReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.next().transpose()? {
+                Some(chunk) if chunk.kind == chunk_kind::IDAT && self.skipped && !self.lenient => {
+                    return Err(PngError::NonConsecutiveIdat.into());
+                }
+                Some(chunk) if chunk.kind == chunk_kind::IDAT => {
+                    chunk.verify()?;
+                    self.current = chunk.data;
+                }
+                None
+                | Some(SliceChunk {
+                    kind: chunk_kind::IEND,
+                    ..
+                }) => {
+                    self.chunks = SliceChunks::new(&[]);
+                    return Ok(0);
+                }
+                // Keep looking, in case image data follows
+                Some(_) => self.skipped = true,
+            }
+        }
+        self.current.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTI_CHUNK: &[u8] = &[
+        0x00, 0x00, 0x00, 0x0a, // len
+        0x49, 0x44, 0x41, 0x54, // IDAT
+        0x78, 0x01, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, // data
+        0x73, 0x75, 0x01, 0x18, // crc
+        0x00, 0x00, 0x00, 0x0a, // len
+        0x49, 0x44, 0x41, 0x54, // IDAT
+        0x78, 0x01, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, // data
+        0x73, 0x75, 0x01, 0x18, // crc
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_slice_chunks() {
+        let chunks = SliceChunks::new(MULTI_CHUNK)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].kind, chunk_kind::IDAT);
+        assert_eq!(chunks[1].data, &MULTI_CHUNK[30..40]);
+        assert_eq!(chunks[2].kind, chunk_kind::IEND);
+        assert!(chunks.iter().all(|c| c.verify().is_ok()));
+
+        let mut truncated = SliceChunks::new(&MULTI_CHUNK[..20]);
+        assert!(truncated.next().unwrap().is_err());
+        assert!(truncated.next().is_none());
+    }
+
+    #[test]
+    fn test_idat_reader() {
+        let mut data = Vec::new();
+        IdatReader::new(SliceChunks::new(MULTI_CHUNK))
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data.len(), 20);
+        assert_eq!(data[..10], MULTI_CHUNK[8..18]);
+        assert_eq!(data[10..], MULTI_CHUNK[30..40]);
+
+        let mut corrupt = MULTI_CHUNK.to_vec();
+        corrupt[40] ^= 0xff;
+        let mut reader = IdatReader::new(SliceChunks::new(&corrupt));
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_lenient() {
+        // Put a tEXt chunk between the two IDAT chunks
+        let text = Chunk::new(chunk_kind::TEXT, b"Title\0Split".to_vec().into());
+        let mut split = MULTI_CHUNK[..22].to_vec();
+        text.write(&mut split).unwrap();
+        split.extend_from_slice(&MULTI_CHUNK[22..]);
+
+        let mut reader = IdatReader::new(SliceChunks::new(&split));
+        let e = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            e.get_ref().and_then(|e| e.downcast_ref()),
+            Some(PngError::NonConsecutiveIdat)
+        ));
+
+        let mut data = Vec::new();
+        IdatReader::new(SliceChunks::new(&split))
+            .lenient(true)
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data.len(), 20);
+    }
+}
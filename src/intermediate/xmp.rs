@@ -0,0 +1,95 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use super::{chunk_kind, Chunk, MAX_CHUNK_LENGTH};
+
+/// iTXt keyword under which XMP packets are stored
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Builds an iTXt chunk holding the XMP `packet`. The packet is stored
+/// uncompressed, so tools scanning files for XMP can find it.
+pub fn xmp_chunk(packet: &str) -> Result<Chunk, &'static str> {
+    let mut data = XMP_KEYWORD.as_bytes().to_vec();
+    data.push(0); // Null separator
+    data.push(0); // Compression flag: uncompressed
+    data.push(0); // Compression method
+    data.push(0); // Empty language tag
+    data.push(0); // Empty translated keyword
+    data.extend_from_slice(packet.as_bytes());
+    if data.len() > MAX_CHUNK_LENGTH as usize {
+        return Err("XMP packet is too large for a chunk");
+    }
+
+    Ok(Chunk::new(chunk_kind::ITXT, data.into_boxed_slice()))
+}
+
+/// Returns the XMP packet stored in `chunk`, or `None` if it isn't an iTXt
+/// chunk with the XMP keyword or its text can't be decoded.
+pub fn xmp_packet(chunk: &Chunk) -> Option<String> {
+    if chunk.kind() != chunk_kind::ITXT {
+        return None;
+    }
+    let rest = chunk
+        .data()
+        .strip_prefix(XMP_KEYWORD.as_bytes())?
+        .strip_prefix(b"\0")?;
+    let [compressed, _method, rest @ ..] = rest else {
+        return None;
+    };
+    // Skip the language tag and translated keyword
+    let mut fields = rest.splitn(3, |&b| b == 0);
+    let text = fields.nth(2)?;
+
+    match compressed {
+        0 => String::from_utf8(text.to_vec()).ok(),
+        1 => {
+            let mut packet = String::new();
+            ZlibDecoder::new(text).read_to_string(&mut packet).ok()?;
+            Some(packet)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use super::*;
+
+    const PACKET: &str = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+
+    #[test]
+    fn test_xmp_chunk() {
+        let chunk = xmp_chunk(PACKET).unwrap();
+        assert_eq!(chunk.kind(), chunk_kind::ITXT);
+        assert!(chunk
+            .data()
+            .starts_with(b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta"));
+        assert_eq!(xmp_packet(&chunk).unwrap(), PACKET);
+    }
+
+    #[test]
+    fn test_compressed_xmp() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(PACKET.as_bytes()).unwrap();
+        let mut data = b"XML:com.adobe.xmp\0\x01\0en\0\0".to_vec();
+        data.extend(encoder.finish().unwrap());
+
+        let chunk = Chunk::new(chunk_kind::ITXT, data.into());
+        assert_eq!(xmp_packet(&chunk).unwrap(), PACKET);
+    }
+
+    #[test]
+    fn test_not_xmp() {
+        let text = Chunk::new(chunk_kind::ITXT, b"Title\0\0\0\0\0Tiny".to_vec().into());
+        assert_eq!(xmp_packet(&text), None);
+
+        let chunk = xmp_chunk(PACKET).unwrap();
+        let text = Chunk::new(chunk_kind::TEXT, chunk.data().into());
+        assert_eq!(xmp_packet(&text), None);
+    }
+}
@@ -1,10 +1,28 @@
 use std::{
     fmt::{LowerHex, UpperHex},
+    io::{Read, Write},
     iter::FusedIterator,
 };
 
-mod intermediate;
+#[cfg(feature = "content-hash")]
+use sha2::{Digest, Sha256};
+
+pub mod batch;
+#[cfg(feature = "differential")]
+pub mod differential;
+pub mod encoder;
+mod error;
+pub mod intermediate;
 pub mod parser;
+mod pixel;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(test)]
+mod test_util;
+
+pub use error::PngError;
+use intermediate::{PhysicalDimensions, Unit};
+pub use pixel::*;
 
 /// 16 bit representation of rgba color
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,6 +53,88 @@ impl Color {
     pub const fn alpha(self) -> u16 {
         self.3
     }
+
+    /// Rec. 709 weighted luma of the (gamma encoded) color channels. Alpha is
+    /// ignored
+    pub const fn luma(self) -> u16 {
+        // Weights scaled to sum to 2^16
+        let Color(r, g, b, _) = self;
+        ((r as u32 * 13933 + g as u32 * 46871 + b as u32 * 4732) >> 16) as u16
+    }
+
+    /// Rec. 709 weighted luminance in linear light, treating the channels as
+    /// sRGB encoded. Alpha is ignored
+    pub fn linear_luma(self) -> u16 {
+        let Color(r, g, b, _) = self;
+        let y =
+            0.2126 * srgb_to_linear(r) + 0.7152 * srgb_to_linear(g) + 0.0722 * srgb_to_linear(b);
+        (y * u16::MAX as f32).round() as u16
+    }
+
+    /// Builds a color from already rounded channels in `0.0..=65535.0`
+    fn from_channels([r, g, b, a]: [f32; 4]) -> Self {
+        Self(r as u16, g as u16, b as u16, a as u16)
+    }
+}
+
+/// How to spread out the rounding error when reducing precision
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Round each channel to the nearest value
+    #[default]
+    None,
+    /// Floyd-Steinberg error diffusion, trading banding for noise
+    FloydSteinberg,
+}
+
+/// Decodes an sRGB encoded channel to linear light in `0.0..=1.0`
+pub(crate) fn srgb_to_linear(channel: u16) -> f32 {
+    let c = channel as f32 / u16::MAX as f32;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes linear light in `0.0..=1.0` as an sRGB channel
+pub(crate) fn linear_to_srgb(linear: f32) -> u16 {
+    let c = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (c.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+/// Describes a caught decoder panic, using its message if it has one
+pub(crate) fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("Decoder panicked: {message}")
+}
+
+/// Securely wipes a buffer of decoded image data. Does nothing unless the
+/// `zeroize` feature is enabled
+#[cfg(feature = "zeroize")]
+pub(crate) fn wipe(buffer: &mut impl zeroize::Zeroize) {
+    buffer.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn wipe<T>(_buffer: &mut T) {}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Color {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+        self.1.zeroize();
+        self.2.zeroize();
+        self.3.zeroize();
+    }
 }
 
 impl UpperHex for Color {
@@ -51,21 +151,61 @@ impl LowerHex for Color {
     }
 }
 
-/// Basically a generic image. Contains no png-specific encocding information
+/// Most pixels [`Png::resample_to_square_pixels`] will produce
+pub const MAX_RESAMPLED_PIXELS: u64 = 1 << 28;
+
+/// Basically a generic image. Contains no png-specific encocding information.
+///
+/// Pixels are stored as `P`, 16 bit rgba [`Color`]s unless converted to a
+/// smaller [`Pixel`] format with [`Png::convert`].
 #[derive(Debug, PartialEq, Eq)]
-pub struct Png {
+pub struct Png<P: Pixel = Color> {
     height: u32,
     width: u32,
-    pixels: Vec<Color>,
+    pixels: Vec<P>,
+    physical: Option<PhysicalDimensions>,
 }
 
-impl Png {
-    pub fn new(height: u32, width: u32, pixels: Vec<Color>) -> Self {
-        Self {
+impl<P: Pixel> Png<P> {
+    /// Creates an image from pixels in row-major order.
+    ///
+    /// # Panics
+    /// Panics if `pixels.len()` isn't `width * height`. See [`Png::try_new`]
+    /// for a non-panicking version.
+    pub fn new(height: u32, width: u32, pixels: Vec<P>) -> Self {
+        Self::try_new(height, width, pixels).expect("Pixel count should match image dimensions")
+    }
+
+    /// Creates an image from pixels in row-major order. Fails if
+    /// `pixels.len()` isn't `width * height`.
+    pub fn try_new(height: u32, width: u32, pixels: Vec<P>) -> Result<Self, &'static str> {
+        if pixels.len() != width as usize * height as usize {
+            return Err("Pixel count doesn't match image dimensions");
+        }
+
+        Ok(Self {
             height,
             width,
             pixels,
+            physical: None,
+        })
+    }
+
+    /// Builds an image from an iterator of pixels in row-major order. Fails if
+    /// the iterator doesn't yield exactly `width * height` pixels.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        pixels: impl IntoIterator<Item = P>,
+    ) -> Result<Self, &'static str> {
+        let len = width as usize * height as usize;
+        let mut pixels = pixels.into_iter();
+        let collected: Vec<P> = pixels.by_ref().take(len).collect();
+        if collected.len() != len || pixels.next().is_some() {
+            return Err("Pixel count doesn't match image dimensions");
         }
+
+        Ok(Self::new(height, width, collected))
     }
 
     pub fn height(&self) -> u32 {
@@ -78,46 +218,486 @@ impl Png {
 
     pub fn pixels(
         &self,
-    ) -> impl Iterator<Item = &Color> + FusedIterator + ExactSizeIterator + DoubleEndedIterator
-    {
+    ) -> impl FusedIterator<Item = &P> + ExactSizeIterator + DoubleEndedIterator {
         self.pixels.iter()
     }
+
+    /// Intended pixel size or aspect ratio, if known
+    pub fn physical_dimensions(&self) -> Option<PhysicalDimensions> {
+        self.physical
+    }
+
+    pub fn with_physical_dimensions(mut self, physical: Option<PhysicalDimensions>) -> Self {
+        self.physical = physical;
+        self
+    }
+
+    /// Intended `(width, height)` of the image in meters, if known
+    pub fn physical_size(&self) -> Option<(f64, f64)> {
+        match self.physical? {
+            PhysicalDimensions {
+                x,
+                y,
+                unit: Unit::Meter,
+            } => Some((self.width as f64 / x as f64, self.height as f64 / y as f64)),
+            _ => None,
+        }
+    }
+
+    /// Width of a pixel divided by its height. Pixels are square (1.0) unless
+    /// the physical dimensions say otherwise
+    pub fn pixel_aspect_ratio(&self) -> f64 {
+        self.physical.map_or(1.0, |p| p.y as f64 / p.x as f64)
+    }
+
+    /// Stretches the image so its pixels are square, making it look right on
+    /// displays with square pixels. The width grows for wide pixels and the
+    /// height grows for tall ones, sampling the nearest source pixel.
+    ///
+    /// Fails if the physical dimensions are zero, or if the result would have
+    /// more than [`MAX_RESAMPLED_PIXELS`] pixels.
+    pub fn resample_to_square_pixels(&self) -> Result<Self, &'static str> {
+        if self.physical.is_some_and(|p| p.x == 0 || p.y == 0) {
+            return Err("Physical dimensions must be non-zero");
+        }
+        let ratio = self.pixel_aspect_ratio();
+        let (width, height) = if ratio >= 1.0 {
+            ((self.width as f64 * ratio).round(), self.height as f64)
+        } else {
+            (self.width as f64, (self.height as f64 / ratio).round())
+        };
+        if width * height > MAX_RESAMPLED_PIXELS as f64 {
+            return Err("Resampled image would be too large");
+        }
+        let (width, height) = (width as u32, height as u32);
+
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let src_x = (x as u64 * self.width as u64 / width as u64) as usize;
+                let src_y = (y as u64 * self.height as u64 / height as u64) as usize;
+                self.pixels[src_y * self.width as usize + src_x]
+            })
+            .collect();
+        let physical = self.physical.map(|p| {
+            let ppu = p.x.max(p.y);
+            PhysicalDimensions {
+                x: ppu,
+                y: ppu,
+                unit: p.unit,
+            }
+        });
+        Ok(Png::new(height, width, pixels).with_physical_dimensions(physical))
+    }
+
+    /// The 16 bit grey level of every pixel, in row-major order. Grey pixels,
+    /// such as those decoded from a greyscale png, give their sample exactly,
+    /// without any rescaling. Other pixels fall back to [`Color::luma`].
+    /// Alpha is ignored.
+    pub fn to_luma16(&self) -> Vec<u16> {
+        self.pixels
+            .iter()
+            .map(|p| Luma16::from_color(p.to_color()).0)
+            .collect()
+    }
+
+    /// Smallest png bit depth (1, 2, 4, 8 or 16) that holds every channel of
+    /// every pixel exactly
+    pub fn bit_depth(&self) -> u8 {
+        let channels = self
+            .pixels
+            .iter()
+            .map(|p| p.to_color())
+            .flat_map(|c| [c.0, c.1, c.2, c.3]);
+        [1, 2, 4, 8]
+            .into_iter()
+            .find(|&bits| {
+                let max = (1u32 << bits) - 1;
+                let step = u16::MAX as u32 / max;
+                channels.clone().all(|v| (v as u32).is_multiple_of(step))
+            })
+            .unwrap_or(16)
+    }
+
+    /// SHA-256 digest of the width, height and pixels, independent of how the
+    /// image was stored. Pixels are hashed as 16 bit big endian RGBA, so the
+    /// same image decoded from any png gives the same hash. Physical
+    /// dimensions aren't included. Needs the `content-hash` feature.
+    #[cfg(feature = "content-hash")]
+    pub fn content_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.width.to_be_bytes());
+        hasher.update(self.height.to_be_bytes());
+        for c in self.pixels.iter().map(|p| p.to_color()) {
+            for channel in [c.0, c.1, c.2, c.3] {
+                hasher.update(channel.to_be_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    /// Consumes the image, returning its pixels in row-major order
+    pub fn into_pixels(mut self) -> Vec<P> {
+        std::mem::take(&mut self.pixels)
+    }
+
+    /// Consumes the image, returning `(height, width, pixels)`. The inverse of
+    /// [`Png::new`]
+    pub fn into_raw_parts(mut self) -> (u32, u32, Vec<P>) {
+        (self.height, self.width, std::mem::take(&mut self.pixels))
+    }
+
+    /// Copies the image into another pixel format, going through 16 bit
+    /// [`Color`]. Physical dimensions are kept.
+    pub fn convert<Q: Pixel>(&self) -> Png<Q> {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|p| Q::from_color(p.to_color()))
+            .collect();
+        Png::new(self.height, self.width, pixels).with_physical_dimensions(self.physical)
+    }
 }
 
-// Below are some of my ideas for storing the various PNG types in a struct. All
-// will have to be modified to support Compression and Interlacing methods. An
-// alternative to all of these would be to just have rgb with 16-bit colors, no
-// matter how they were stored in the png. At that point, it's just an image,
-// not really a png, and you could end up with a situation where my library
-// encodes it differently than it was originally decoded.
+impl Png {
+    /// Quantizes every channel to `bits` of precision, which must be a png bit
+    /// depth. Channels are still stored as 16 bit values, scaled the same way
+    /// as when decoding an image of that depth, so the result has a
+    /// [`Png::bit_depth`] of at most `bits`.
+    pub fn demote_depth(&self, bits: u8, dither: Dither) -> Result<Png, &'static str> {
+        if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+            return Err("Bit depth must be 1, 2, 4, 8 or 16");
+        }
+        let max = ((1u32 << bits) - 1) as f32;
+        let step = u16::MAX as f32 / max;
+        let quantize = |v: f32| ((v / step).round().clamp(0.0, max) * step).round();
 
-/// Generic Png: Color trait implemented by kinds
-/// Pros:
-/// * Forces conversion of all pixels if you want to change from one color to
-///   another
-/// Cons:
-/// * Have to know color type at compile time. Doesn't seem feasible
-mod generic {
-    pub trait Color {
-        fn rgba(&self) -> (u8, u8, u8, u8);
-
-        // Does this make sense to add?
-        fn rgb(&self) -> (u8, u8, u8) {
-            let (r, g, b, _) = self.rgba();
-            (r, g, b)
+        let mut pixels = self.pixels.clone();
+        match dither {
+            Dither::None => {
+                for c in &mut pixels {
+                    *c = Color::from_channels([c.0, c.1, c.2, c.3].map(|v| quantize(v as f32)));
+                }
+            }
+            Dither::FloydSteinberg => {
+                // Error carried to the current and next row, with a pixel of
+                // padding on each side
+                let width = self.width as usize;
+                let mut current = vec![[0f32; 4]; width + 2];
+                let mut next = vec![[0f32; 4]; width + 2];
+                for row in pixels.chunks_mut(width.max(1)) {
+                    for (x, c) in row.iter_mut().enumerate() {
+                        let mut channels = [0f32; 4];
+                        for (i, v) in [c.0, c.1, c.2, c.3].into_iter().enumerate() {
+                            let wanted = v as f32 + current[x + 1][i];
+                            channels[i] = quantize(wanted);
+                            let error = wanted - channels[i];
+                            current[x + 2][i] += error * 7.0 / 16.0;
+                            next[x][i] += error * 3.0 / 16.0;
+                            next[x + 1][i] += error * 5.0 / 16.0;
+                            next[x + 2][i] += error / 16.0;
+                        }
+                        *c = Color::from_channels(channels);
+                    }
+                    std::mem::swap(&mut current, &mut next);
+                    next.fill([0.0; 4]);
+                }
+            }
         }
+        Ok(Png::new(self.height, self.width, pixels).with_physical_dimensions(self.physical))
+    }
 
-        fn hex(&self) -> String {
-            let (r, g, b) = self.rgb();
-            format!("#{r:X}{g:X}{b:X}")
+    /// Expands channels holding `bits` bit samples in their high bits, as
+    /// left by a plain shift (e.g. `0xab00` for the 8 bit sample `0xab`), to
+    /// the full 16 bit range, scaled the same way as when decoding an image
+    /// of that depth. Lower bits are ignored. `bits` must be a png bit depth;
+    /// 16 leaves the image as is.
+    pub fn promote_depth(&self, bits: u8) -> Result<Png, &'static str> {
+        if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+            return Err("Bit depth must be 1, 2, 4, 8 or 16");
         }
+        let step = u16::MAX / ((1u32 << bits) - 1) as u16;
+        let expand = |v: u16| (v >> (16 - bits)) * step;
+
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|c| Color(expand(c.0), expand(c.1), expand(c.2), expand(c.3)))
+            .collect();
+        Ok(Png::new(self.height, self.width, pixels).with_physical_dimensions(self.physical))
     }
+}
 
-    pub struct Png<C: Color> {
-        pixels: Vec<C>,
+/// With the `zeroize` feature, pixels are wiped when the image is dropped.
+/// Pixels taken out with [`Png::into_pixels`] or [`Png::into_raw_parts`] are
+/// not, as they are no longer owned by the image.
+#[cfg(feature = "zeroize")]
+impl<P: Pixel> Drop for Png<P> {
+    fn drop(&mut self) {
+        P::wipe(&mut self.pixels);
     }
 }
 
+/// Decodes a png with the default settings, including the
+/// [`parser::MAX_PIXELS`] limit. The whole stream is read into memory first,
+/// and wiped afterwards with the `zeroize` feature; see [`parser::PngParser`]
+/// for streaming and finer control.
+pub fn decode(mut reader: impl Read) -> Result<Png, PngError> {
+    let mut bytes = Vec::new();
+    let result = reader
+        .read_to_end(&mut bytes)
+        .and_then(|_| parser::decode(&bytes));
+    wipe(&mut bytes);
+    Ok(result?)
+}
+
+/// Encodes a png with settings picked from the image: the smallest color type
+/// and bit depth that fit, adaptive filtering and default compression. See
+/// [`encoder::encode`].
+pub fn encode(png: &Png, writer: impl Write) -> Result<(), PngError> {
+    Ok(encoder::encode(png, writer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TINY_PNG;
+
+    const B: Color = Color::new_opaque(0, 0, 0);
+
+    #[test]
+    fn test_decode() {
+        let png = decode(TINY_PNG).unwrap();
+        assert_eq!(png.into_pixels(), [B]);
+
+        let error = decode(&TINY_PNG[..40]).unwrap_err();
+        assert!(matches!(error, PngError::Io(_)));
+
+        let mut bad_compression = TINY_PNG.to_vec();
+        bad_compression[26] = 1;
+        let crc = intermediate::crc32(&bad_compression[12..29]);
+        bad_compression[29..33].copy_from_slice(&crc.to_be_bytes());
+        assert!(matches!(
+            decode(&bad_compression[..]),
+            Err(PngError::Format(e)) if e == "Unknown compression method"
+        ));
+    }
+
+    #[test]
+    fn test_encode() {
+        let png = Png::new(1, 2, vec![B, Color::new(1, 2, 3, 4)]);
+        let mut encoded = Vec::new();
+        encode(&png, &mut encoded).unwrap();
+        assert_eq!(decode(&encoded[..]).unwrap(), png);
+
+        let empty = Png::new(0, 0, Vec::new());
+        assert!(matches!(encode(&empty, Vec::new()), Err(PngError::Io(_))));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_wipe() {
+        let mut pixels = vec![Color::new(1, 2, 3, 4); 4];
+        wipe(&mut pixels);
+        assert!(pixels.is_empty());
+
+        let mut color = Color::new(1, 2, 3, 4);
+        wipe(&mut color);
+        assert_eq!(color, Color::new(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_luma() {
+        let w = Color::new_opaque(u16::MAX, u16::MAX, u16::MAX);
+        assert_eq!(B.luma(), 0);
+        assert_eq!(w.luma(), u16::MAX);
+        assert_eq!(Color::new_opaque(u16::MAX, 0, 0).luma(), 13932);
+        assert_eq!(Color::new(0x8080, 0x8080, 0x8080, 0).luma(), 0x8080);
+
+        assert_eq!(B.linear_luma(), 0);
+        assert_eq!(w.linear_luma(), u16::MAX);
+        // sRGB mid grey is about 21.6% luminance
+        let grey = Color::new_opaque(0x8080, 0x8080, 0x8080).linear_luma();
+        assert!((14100..14200).contains(&grey), "{grey}");
+        // Green contributes more than red, which contributes more than blue
+        let red = Color::new_opaque(u16::MAX, 0, 0).linear_luma();
+        let green = Color::new_opaque(0, u16::MAX, 0).linear_luma();
+        let blue = Color::new_opaque(0, 0, u16::MAX).linear_luma();
+        assert!(green > red && red > blue);
+    }
+
+    #[test]
+    fn test_from_pixels() {
+        let image = Png::from_pixels(3, 2, std::iter::repeat_n(B, 6)).unwrap();
+        assert_eq!(image.width(), 3);
+        assert_eq!(image.height(), 2);
+        assert_eq!(image.pixels().len(), 6);
+
+        assert!(Png::from_pixels(3, 2, std::iter::repeat_n(B, 5)).is_err());
+        assert!(Png::from_pixels(3, 2, std::iter::repeat(B)).is_err());
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert!(Png::try_new(2, 3, vec![B; 6]).is_ok());
+        assert!(Png::try_new(2, 3, vec![B; 5]).is_err());
+        assert!(Png::try_new(2, 3, vec![B; 7]).is_err());
+        assert!(Png::try_new(0, 3, Vec::<Color>::new()).is_ok());
+    }
+
+    #[test]
+    fn test_into_raw_parts() {
+        let image = Png::new(2, 3, vec![B; 6]);
+        assert_eq!(image.into_raw_parts(), (2, 3, vec![B; 6]));
+
+        let image = Png::new(2, 3, vec![B; 6]);
+        assert_eq!(image.into_pixels(), vec![B; 6]);
+    }
+
+    #[test]
+    fn test_physical_dimensions() {
+        let w = Color::new_opaque(u16::MAX, u16::MAX, u16::MAX);
+        let image = Png::new(1, 2, vec![B, w]);
+        assert_eq!(image.physical_size(), None);
+        assert_eq!(image.pixel_aspect_ratio(), 1.0);
+        assert_eq!(image.resample_to_square_pixels().unwrap(), image);
+
+        // Pixels twice as wide as they are tall
+        let image = image.with_physical_dimensions(Some(PhysicalDimensions {
+            x: 1000,
+            y: 2000,
+            unit: Unit::Meter,
+        }));
+        assert_eq!(image.physical_size(), Some((0.002, 0.0005)));
+        assert_eq!(image.pixel_aspect_ratio(), 2.0);
+
+        let square = image.resample_to_square_pixels().unwrap();
+        assert_eq!((square.width(), square.height()), (4, 1));
+        assert_eq!(square.pixel_aspect_ratio(), 1.0);
+        assert_eq!(square.physical_size(), Some((0.002, 0.0005)));
+        assert_eq!(square.into_pixels(), vec![B, B, w, w]);
+
+        let stretched = |x, y| {
+            Png::new(1, 1, vec![B]).with_physical_dimensions(Some(PhysicalDimensions {
+                x,
+                y,
+                unit: Unit::Unknown,
+            }))
+        };
+        assert!(stretched(1, u32::MAX).resample_to_square_pixels().is_err());
+        assert!(stretched(u32::MAX, 1).resample_to_square_pixels().is_err());
+        assert!(stretched(0, 1).resample_to_square_pixels().is_err());
+    }
+
+    #[test]
+    fn test_demote_depth() {
+        let grey = |v| Color::new_opaque(v, v, v);
+        let image = Png::new(1, 3, vec![grey(0), grey(0x1234), grey(0x8000)]);
+        assert_eq!(image.bit_depth(), 16);
+
+        let demoted = image.demote_depth(8, Dither::None).unwrap();
+        assert_eq!(demoted.bit_depth(), 8);
+        assert_eq!(
+            demoted.into_pixels(),
+            vec![grey(0), grey(0x1212), grey(0x8080)]
+        );
+
+        let demoted = image.demote_depth(1, Dither::None).unwrap();
+        assert_eq!(demoted.bit_depth(), 1);
+        assert_eq!(demoted.pixels().last(), Some(&grey(u16::MAX)));
+
+        assert!(image.demote_depth(3, Dither::None).is_err());
+        assert_eq!(
+            image.demote_depth(16, Dither::FloydSteinberg).unwrap(),
+            image
+        );
+    }
+
+    #[test]
+    fn test_promote_depth() {
+        let grey = |v| Color::new_opaque(v, v, v);
+        let shifted = Png::new(1, 3, vec![grey(0), grey(0x1200), grey(0x80ff)]);
+
+        let promoted = shifted.promote_depth(8).unwrap();
+        assert_eq!(promoted.bit_depth(), 8);
+        assert_eq!(
+            promoted.into_pixels(),
+            vec![grey(0), grey(0x1212), grey(0x8080)]
+        );
+
+        let promoted = shifted.promote_depth(1).unwrap();
+        assert_eq!(promoted.pixels().last(), Some(&grey(u16::MAX)));
+
+        assert_eq!(shifted.promote_depth(16).unwrap(), shifted);
+        assert!(shifted.promote_depth(3).is_err());
+    }
+
+    #[test]
+    fn test_dither() {
+        // Mid grey averages out instead of all rounding the same way
+        let grey = Color::new_opaque(0x7fff, 0x7fff, 0x7fff);
+        let image = Png::new(4, 4, vec![grey; 16]);
+        let plain = image.demote_depth(1, Dither::None).unwrap();
+        assert!(plain.pixels().all(|&c| c == plain.pixels[0]));
+
+        let dithered = image.demote_depth(1, Dither::FloydSteinberg).unwrap();
+        assert_eq!(dithered.bit_depth(), 1);
+        let white = dithered.pixels().filter(|c| c.red() == u16::MAX).count();
+        assert_eq!(white, 8);
+    }
+
+    #[test]
+    fn test_to_luma16() {
+        let png = Png::new(
+            1,
+            3,
+            vec![
+                Color::new_opaque(0x1234, 0x1234, 0x1234),
+                Color::new(0xfffe, 0xfffe, 0xfffe, 0),
+                Color::new_opaque(u16::MAX, 0, 0),
+            ],
+        );
+        assert_eq!(png.to_luma16(), [0x1234, 0xfffe, 13932]);
+    }
+
+    #[test]
+    #[cfg(feature = "content-hash")]
+    fn test_content_hash() {
+        let w = Color::new_opaque(u16::MAX, u16::MAX, u16::MAX);
+        let image = Png::new(1, 2, vec![B, w]);
+        assert_eq!(image.content_hash()[..4], [0xc8, 0x14, 0xc5, 0x8a]);
+
+        let physical = Some(PhysicalDimensions {
+            x: 1,
+            y: 2,
+            unit: Unit::Unknown,
+        });
+        let tagged = Png::new(1, 2, vec![B, w]).with_physical_dimensions(physical);
+        assert_eq!(tagged.content_hash(), image.content_hash());
+        assert_ne!(
+            Png::new(2, 1, vec![B, w]).content_hash(),
+            image.content_hash()
+        );
+        assert_ne!(
+            Png::new(1, 2, vec![w, B]).content_hash(),
+            image.content_hash()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_new_mismatched() {
+        Png::new(2, 3, vec![B; 5]);
+    }
+}
+
+// Below are some of my ideas for storing the various PNG types in a struct. All
+// will have to be modified to support Compression and Interlacing methods. An
+// alternative to all of these would be to just have rgb with 16-bit colors, no
+// matter how they were stored in the png. At that point, it's just an image,
+// not really a png, and you could end up with a situation where my library
+// encodes it differently than it was originally decoded.
+
 /// Global Color Png
 /// Have to parse pixels as needed
 /// Still not to difficult:
@@ -129,6 +709,7 @@ mod generic {
 ///   More memory intensive, especially for lower-quality greyscale/indexed-color
 /// Easy to save, since pixel_data should be the same as IDAT data (without compression/interlacing anyways)
 ///   I don't know that this is true
+#[allow(dead_code)]
 mod global {
     use super::intermediate::ColorKind;
 
@@ -145,6 +726,7 @@ mod global {
 /// doesn't make sense.
 /// The struct Color storing the data on the heap means that to access the color
 /// values you need to access two pointers
+#[allow(dead_code)]
 mod individual_struct {
     use super::intermediate::ColorKind;
 
@@ -160,6 +742,7 @@ mod individual_struct {
 
 /// This comes with the same memory issues as storing all colors as u32 in the
 /// global example.
+#[allow(dead_code)]
 mod individual_enum {
     pub enum Color {
         GreyAlpha(/* data*/),
@@ -173,6 +756,7 @@ mod individual_enum {
 /// Lossy Png - Really just Image, with a Png parser
 /// Just use 16 bit rgb values in the struct, leave the decoding/encoding
 /// decisions to encoding/decoding time
+#[allow(dead_code)]
 mod lossy {
     pub struct Color(u16, u16, u16);
     pub struct Png {
@@ -1,49 +1,278 @@
-use std::io::{self, Error, ErrorKind, Read, Seek};
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Error, ErrorKind, Read, Seek, SeekFrom},
+    iter,
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
+};
 
-use flate2::read::{DeflateDecoder, ZlibDecoder};
+use flate2::read::ZlibDecoder;
 
 use crate::{
     intermediate::{
         self,
         chunk_reader::ChunkReader,
-        filter::{Filter, FilterKind},
-        Chunk, ChunkKind, ColorKind, PngColor,
+        exif::Exif,
+        filter::{self, Filter},
+        slice_reader::{IdatReader, SliceChunks},
+        xmp, Chunk, ChunkKind, ColorKind, Interlace, PhysicalDimensions, PngColor, ADAM7_PASSES,
     },
-    Color, Png,
+    Color, Pixel, Png,
 };
 
 const PNG_SIG: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+/// Largest width or height the spec allows
+const MAX_DIMENSION: u32 = 2u32.pow(31) - 1;
+
+/// Most pixels an image can have for the parser to decode it, unless changed
+/// with [`PngParser::max_pixels`]
+pub const MAX_PIXELS: u64 = 1 << 28;
+
+/// Factor to shrink an image by while decoding it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Half,
+    Quarter,
+    Eighth,
+}
+
+/// How pixels are averaged when scaling
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeOptions {
+    /// Average in linear light rather than on the sRGB encoded values. Slower,
+    /// but stops downscaled images from looking darker than the original
+    pub linear_light: bool,
+    /// Weight each pixel's color by its alpha, so the color of (nearly)
+    /// transparent pixels doesn't bleed into visible ones as dark halos
+    pub premultiply_alpha: bool,
+}
+
+impl Scale {
+    /// Number of source pixels along each side of an output pixel
+    pub const fn factor(self) -> u32 {
+        match self {
+            Self::Half => 2,
+            Self::Quarter => 4,
+            Self::Eighth => 8,
+        }
+    }
+}
+
+/// What the parser does with an ancillary chunk it comes across
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkAction {
+    /// Skip over the chunk without reading its data
+    #[default]
+    Drop,
+    /// Read the chunk and keep its raw data, available from
+    /// [`PngParser::chunks`]
+    Keep,
+}
+
+/// What the parser does when an ancillary chunk is corrupt
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Fail to parse the image
+    #[default]
+    Fail,
+    /// Drop the chunk and scan forward for the next intact chunk. See
+    /// [`intermediate::resync`]
+    Resync,
+}
+
+/// Decides which ancillary chunks the parser keeps. Chunks without an
+/// explicit action get the default action, which is [`ChunkAction::Drop`]
+/// unless changed. Used by both [`PngParser::with_chunk_filter`] and
+/// [`PngParser::from_bytes_with_chunk_filter`].
+///
+/// Limits:
+/// - Only chunks before the image data are seen. Chunks after IDAT are never
+///   read, whatever their action.
+/// - There is no separate action for parsing a chunk. pHYs is always parsed,
+///   and kept chunks can be parsed afterwards, e.g. with
+///   [`PngParser::exif`] and [`PngParser::xmp`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChunkFilter {
+    default: ChunkAction,
+    actions: HashMap<ChunkKind, ChunkAction>,
+    recovery: Recovery,
+    lenient_idat: bool,
+}
+
+impl ChunkFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the action for chunks without an explicit action
+    pub fn default_action(mut self, action: ChunkAction) -> Self {
+        self.default = action;
+        self
+    }
+
+    /// Sets the action for chunks of the given kind
+    pub fn action(mut self, kind: ChunkKind, action: ChunkAction) -> Self {
+        self.actions.insert(kind, action);
+        self
+    }
+
+    pub fn keep(self, kind: ChunkKind) -> Self {
+        self.action(kind, ChunkAction::Keep)
+    }
+
+    pub fn drop(self, kind: ChunkKind) -> Self {
+        self.action(kind, ChunkAction::Drop)
+    }
+
+    /// The action to take for chunks of the given kind
+    pub fn action_for(&self, kind: ChunkKind) -> ChunkAction {
+        self.actions.get(&kind).copied().unwrap_or(self.default)
+    }
+
+    /// Sets what happens when an ancillary chunk is corrupt, either a bad crc
+    /// or a length that doesn't lead to the next chunk
+    pub fn recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Skips chunks between IDAT chunks instead of failing with
+    /// [`PngError::NonConsecutiveIdat`](crate::PngError::NonConsecutiveIdat)
+    pub fn lenient_idat(mut self, lenient: bool) -> Self {
+        self.lenient_idat = lenient;
+        self
+    }
+}
+
 /// Struct for parsing a png
 /// https://www.w3.org/TR/png-3
 ///
+/// `R` reads the image data, usually a [`ChunkReader`] over a stream (see
+/// [`PngParser::new`]) or an [`IdatReader`] over a slice (see
+/// [`PngParser::from_bytes`]).
+///
 /// E           D
 /// | interlace ^
 /// | filter    |
 /// | compress  |
 /// v chunk     |
 pub struct PngParser<R> {
-    reader: ZlibDecoder<ChunkReader<R>>,
+    reader: ZlibDecoder<R>,
     width: u32,
     height: u32,
     color: PngColor,
-    interlace_method: u8,
-    filter: Filter,
+    interlace: Interlace,
+    filter: Box<dyn Filter>,
+    #[allow(dead_code)]
     compression_method: u8,
+    chunks: Vec<Chunk>,
+    physical: Option<PhysicalDimensions>,
+    /// Number of passes read so far by [`PngParser::next_pass`]
+    pass: usize,
+    max_pixels: Option<u64>,
 }
 
 impl<R> PngParser<R> {
-    fn scanline_length(&self) -> usize {
-        // TODO: change for interlace method and pass #
-        self.width as usize * self.color.data_len().div_ceil(8) + 1
+    /// Width of the image in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the image in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Color type the image data is stored as
+    pub fn color_kind(&self) -> ColorKind {
+        self.color.kind()
+    }
+
+    /// Number of bits per sample (or per palette index)
+    pub fn bit_depth(&self) -> u8 {
+        self.color.depth()
+    }
+
+    /// Interlace method the image data is stored with
+    pub fn interlace(&self) -> Interlace {
+        self.interlace
+    }
+
+    /// Replaces the filter used to reconstruct scanlines, which otherwise
+    /// comes from the filter method in the header
+    pub fn with_filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filter = Box::new(filter);
+        self
+    }
+
+    /// Sets the most pixels the image may have, or `None` for no limit.
+    /// Decoding a larger image fails before any image data is read. Defaults
+    /// to [`MAX_PIXELS`]
+    pub fn max_pixels(mut self, max: Option<u64>) -> Self {
+        self.max_pixels = max;
+        self
+    }
+
+    /// Intended pixel size or aspect ratio, from the pHYs chunk
+    pub fn physical_dimensions(&self) -> Option<PhysicalDimensions> {
+        self.physical
+    }
+
+    /// Ancillary chunks kept by the [`ChunkFilter`], in the order they
+    /// appeared
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// The XMP packet, if the image has one. Only found if iTXt chunks are
+    /// kept by the [`ChunkFilter`]
+    pub fn xmp(&self) -> Option<String> {
+        self.chunks.iter().find_map(xmp::xmp_packet)
+    }
+
+    /// Tags from the eXIf chunk, if the image has one that can be parsed.
+    /// Only found if eXIf chunks are kept by the [`ChunkFilter`]
+    pub fn exif(&self) -> Option<Exif> {
+        self.chunks
+            .iter()
+            .find(|c| c.kind() == intermediate::EXIF)
+            .and_then(|c| Exif::try_from(c.data()).ok())
+    }
+
+    /// Fails if the image has more pixels than allowed by
+    /// [`PngParser::max_pixels`]
+    fn check_size(&self) -> io::Result<()> {
+        let pixels = self.width as u64 * self.height as u64;
+        if self.max_pixels.is_some_and(|max| pixels > max) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Image has more pixels than allowed",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Length in bytes of a scanline `width` pixels wide, including the
+    /// filter type byte
+    fn scanline_length(&self, width: u32) -> usize {
+        (width as usize * self.color.data_len()).div_ceil(8) + 1
     }
 }
 
-impl<R> PngParser<R>
+impl<R> PngParser<ChunkReader<R>>
 where
     R: Read + Seek,
 {
-    pub fn new(mut reader: R) -> io::Result<Self> {
+    /// Reads the png header, dropping all ancillary chunks before the image
+    /// data
+    pub fn new(reader: R) -> io::Result<Self> {
+        Self::with_chunk_filter(reader, ChunkFilter::default())
+    }
+
+    /// Reads the png header, keeping or dropping ancillary chunks before the
+    /// image data as decided by `chunk_filter`
+    pub fn with_chunk_filter(mut reader: R, chunk_filter: ChunkFilter) -> io::Result<Self> {
         let mut sig = [0u8; 8];
         reader.read_exact(&mut sig)?;
         if sig != PNG_SIG {
@@ -54,6 +283,190 @@ where
         }
 
         let header = Chunk::read(&mut reader)?;
+        header.verify()?;
+
+        // read chunks until first IDAT chunk
+        let mut chunks = Vec::new();
+        let mut physical = None;
+        let mut bound = [0u8; 8];
+        let mut chunk_kind = next_chunk_kind(&mut reader, &mut bound)?;
+
+        while chunk_kind != intermediate::IDAT {
+            if chunk_kind.critical() && chunk_kind != intermediate::PLTE {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Unrecognized critical chunk",
+                ));
+            }
+
+            let start = reader.stream_position()?;
+            let action = chunk_filter.action_for(chunk_kind);
+            let result = match chunk_kind {
+                // Chunks the parser understands are always read
+                intermediate::PHYS => read_verified(&mut reader)
+                    .and_then(|chunk| keep_ancillary(chunk, action, &mut chunks, &mut physical)),
+                _ if action == ChunkAction::Keep && !chunk_kind.critical() => {
+                    read_verified(&mut reader).map(|chunk| chunks.push(chunk))
+                }
+                _ => {
+                    let len = u32::from_be_bytes(*bound.first_chunk::<4>().expect("8 > 4"));
+                    reader.seek_relative(len as i64 + 12) // Skip length, kind, data and crc
+                }
+            };
+
+            chunk_kind = match result.and_then(|()| next_chunk_kind(&mut reader, &mut bound)) {
+                Ok(kind) => kind,
+                Err(e)
+                    if chunk_filter.recovery == Recovery::Resync
+                        && matches!(
+                            e.kind(),
+                            ErrorKind::InvalidData | ErrorKind::UnexpectedEof
+                        ) =>
+                {
+                    // Skip the header of the bad chunk, which may itself look intact
+                    reader.seek(SeekFrom::Start(start + 8))?;
+                    intermediate::resync(&mut reader)?;
+                    next_chunk_kind(&mut reader, &mut bound)?
+                }
+                Err(e) => return Err(e),
+            };
+        }
+        // next chunk up is IDAT
+
+        let reader = ChunkReader::new(reader)?.lenient(chunk_filter.lenient_idat);
+        Self::from_header(&header, reader, chunks, physical)
+    }
+}
+
+impl<'a> PngParser<IdatReader<'a>> {
+    /// Reads the png header from a png held in memory, dropping all ancillary
+    /// chunks before the image data. Chunks are found by index arithmetic on
+    /// the slice and image data is read from it directly, skipping the
+    /// [`Read`] and [`Seek`] layers of [`PngParser::new`].
+    pub fn from_bytes(bytes: &'a [u8]) -> io::Result<Self> {
+        Self::from_bytes_with_chunk_filter(bytes, ChunkFilter::default())
+    }
+
+    /// Reads the png header from a png held in memory, keeping or dropping
+    /// ancillary chunks before the image data as decided by `chunk_filter`.
+    /// The slice counterpart of [`PngParser::with_chunk_filter`]
+    pub fn from_bytes_with_chunk_filter(
+        bytes: &'a [u8],
+        chunk_filter: ChunkFilter,
+    ) -> io::Result<Self> {
+        let rest = bytes
+            .strip_prefix(&PNG_SIG)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "PNG missing signature"))?;
+        let missing = || Error::new(ErrorKind::UnexpectedEof, "PNG ended before the image data");
+
+        let mut slice_chunks = SliceChunks::new(rest);
+        let header = slice_chunks.next().ok_or_else(missing)??.to_chunk()?;
+
+        let mut chunks = Vec::new();
+        let mut physical = None;
+        loop {
+            let start = slice_chunks.rest();
+            let chunk = slice_chunks.next().ok_or_else(missing)?;
+            match chunk {
+                Ok(ref chunk) if chunk.kind == intermediate::IDAT => {
+                    // Rewind so the IDAT chunk is read as image data
+                    slice_chunks = SliceChunks::new(start);
+                    break;
+                }
+                Ok(ref chunk) if chunk.kind.critical() && chunk.kind != intermediate::PLTE => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Unrecognized critical chunk",
+                    ));
+                }
+                _ => (),
+            }
+
+            let result = chunk.and_then(|chunk| {
+                let action = chunk_filter.action_for(chunk.kind);
+                match chunk.kind {
+                    // Chunks the parser understands are always read
+                    intermediate::PHYS => chunk
+                        .to_chunk()
+                        .and_then(|c| keep_ancillary(c, action, &mut chunks, &mut physical)),
+                    kind if action == ChunkAction::Keep && !kind.critical() => {
+                        chunk.to_chunk().map(|c| chunks.push(c))
+                    }
+                    _ => Ok(()),
+                }
+            });
+
+            match result {
+                Ok(()) => (),
+                Err(e)
+                    if chunk_filter.recovery == Recovery::Resync
+                        && matches!(
+                            e.kind(),
+                            ErrorKind::InvalidData | ErrorKind::UnexpectedEof
+                        ) =>
+                {
+                    // Skip the header of the bad chunk, which may itself look intact
+                    let mut cursor = Cursor::new(start.get(8..).unwrap_or_default());
+                    intermediate::resync(&mut cursor)?;
+                    let found = &cursor.get_ref()[cursor.position() as usize..];
+                    slice_chunks = SliceChunks::new(found);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let reader = IdatReader::new(slice_chunks).lenient(chunk_filter.lenient_idat);
+        Self::from_header(&header, reader, chunks, physical)
+    }
+}
+
+/// Reads the next chunk, failing if its crc doesn't match
+fn read_verified(reader: &mut impl Read) -> io::Result<Chunk> {
+    let chunk = Chunk::read(reader)?;
+    chunk.verify()?;
+    Ok(chunk)
+}
+
+/// Handles an ancillary chunk the parser understands, keeping it if `action`
+/// says so. Malformed contents are ignored, as the image can still be decoded
+/// without them
+fn keep_ancillary(
+    chunk: Chunk,
+    action: ChunkAction,
+    chunks: &mut Vec<Chunk>,
+    physical: &mut Option<PhysicalDimensions>,
+) -> io::Result<()> {
+    if chunk.kind() == intermediate::PHYS {
+        *physical = PhysicalDimensions::try_from(chunk.data()).ok();
+    }
+    if action == ChunkAction::Keep {
+        chunks.push(chunk);
+    }
+    Ok(())
+}
+
+/// Reads the length and kind of the next chunk into `bound` without
+/// consuming them
+fn next_chunk_kind(reader: &mut (impl Read + Seek), bound: &mut [u8; 8]) -> io::Result<ChunkKind> {
+    reader.read_exact(bound)?;
+    let kind = ChunkKind::try_from(bound[4..].first_chunk::<4>().expect("4 = 4"))
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    reader.seek_relative(-8)?; // Should be always safe
+    Ok(kind)
+}
+
+impl<R> PngParser<R>
+where
+    R: Read,
+{
+    /// Builds a parser from the IHDR chunk, reading the image data from
+    /// `reader`
+    fn from_header(
+        header: &Chunk,
+        reader: R,
+        chunks: Vec<Chunk>,
+        physical: Option<PhysicalDimensions>,
+    ) -> io::Result<Self> {
         if header.kind() != intermediate::IHDR || header.len() != 13 {
             return Err(Error::new(
                 ErrorKind::InvalidData,
@@ -65,6 +478,12 @@ where
         let width = u32::from_be_bytes(*header_data.first_chunk::<4>().expect("Checked above"));
         let height =
             u32::from_be_bytes(*header_data[4..].first_chunk::<4>().expect("Checked above"));
+        if !(1..=MAX_DIMENSION).contains(&width) || !(1..=MAX_DIMENSION).contains(&height) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Image dimensions out of range",
+            ));
+        }
 
         let bit_depth = header_data[8];
         let color_kind = ColorKind::try_from(header_data[9])
@@ -73,91 +492,419 @@ where
         let color = PngColor::new(color_kind, bit_depth)
             .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
 
-        let interlace_method = header_data[12];
-        let filter =
-            Filter::try_from(header_data[11]).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let interlace = Interlace::try_from(header_data[12])
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let filter = filter::from_method(header_data[11])
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
 
         let compression_method = header_data[10];
-        assert!(compression_method == 0); // Panic for compressed pngs for now
-
-        // read chunks (and ignore) until first IDAT chunk
-        let mut kind_bytes = [0u8; 4];
-        reader.seek_relative(4)?; // Skip length
-        reader.read_exact(&mut kind_bytes)?;
-        let mut chunk_kind =
-            ChunkKind::try_from(&kind_bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-        reader.seek_relative(-8)?; // Should be always safe
-
-        while chunk_kind != intermediate::IDAT {
-            assert!(chunk_kind.critical()); // Throwing away, so can't be critical
-            println!("Throwing away {:?}", chunk_kind);
-
-            _ = Chunk::read(&mut reader)?;
-
-            reader.seek_relative(4)?; // Skip length
-            reader.read_exact(&mut kind_bytes)?;
-            chunk_kind = ChunkKind::try_from(&kind_bytes)
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-            reader.seek_relative(-8)?; // Should be always safe
+        if compression_method != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Unknown compression method",
+            ));
         }
-        // next chunk up is IDAT
 
         Ok(Self {
-            reader: ZlibDecoder::new(ChunkReader::new(reader)?),
+            reader: ZlibDecoder::new(reader),
             width,
             height,
             color,
-            interlace_method,
+            interlace,
             filter,
             compression_method,
+            chunks,
+            physical,
+            pass: 0,
+            max_pixels: Some(MAX_PIXELS),
         })
     }
-}
 
-impl<R> PngParser<R>
-where
-    R: Read,
-{
     /// E           D
     /// | interlace ^
     /// | filter    |
     /// | compress  |
     /// v chunk     |
     pub fn parse(mut self) -> Result<Png, io::Error> {
-        // De-filter
+        if self.interlace == Interlace::None {
+            let pixels = self.read_pass(self.width, self.height)?;
+            return Ok(
+                Png::new(self.height, self.width, pixels).with_physical_dimensions(self.physical)
+            );
+        }
+
+        // De-interlace by placing each pass's pixels into the full image. The
+        // full image is only allocated once every pass has been read, so the
+        // data has to back up the size in the header
+        self.check_size()?;
+        let passes = iter::from_fn(|| self.next_pass()).collect::<io::Result<Vec<_>>>()?;
+        let width = self.width as usize;
+        let mut pixels = vec![Color::new(0, 0, 0, 0); width * self.height as usize];
+        for (pass, reduced) in ADAM7_PASSES.into_iter().zip(passes) {
+            let (pass_width, _) = pass.size(self.width, self.height);
+            let mut reduced = reduced.into_pixels();
+            for (i, &c) in reduced.iter().enumerate() {
+                let x = pass.x as usize + i % pass_width as usize * pass.dx as usize;
+                let y = pass.y as usize + i / pass_width as usize * pass.dy as usize;
+                pixels[y * width + x] = c;
+            }
+            crate::wipe(&mut reduced);
+        }
+        Ok(Png::new(self.height, self.width, pixels).with_physical_dimensions(self.physical))
+    }
 
+    /// Like [`PngParser::parse`], storing pixels as `P`. Non-interlaced
+    /// images are converted a scanline at a time, so the whole image is never
+    /// held as 16 bit [`Color`]s, and images of 8 bits or less go through
+    /// [`Pixel::from_rgba8`] without being widened. Interlaced images are
+    /// decoded in full and then converted.
+    pub fn parse_as<P: Pixel>(mut self) -> Result<Png<P>, io::Error> {
+        if self.interlace == Interlace::Adam7 {
+            return self.parse().map(|png| png.convert());
+        }
+
+        let width = self.width as usize;
+        let mut pixels = Vec::new();
+        let result = self.read_scanlines(self.width, self.height, |color, data| {
+            let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+            if color.depth() <= 8 {
+                let mut line = color.parse_8(data).map_err(invalid)?;
+                pixels.extend(line[..width].iter().map(|&c| P::from_rgba8(c)));
+                crate::wipe(&mut line);
+            } else {
+                let mut line = color.parse(data).map_err(invalid)?;
+                pixels.extend(line[..width].iter().map(|&c| P::from_color(c)));
+                crate::wipe(&mut line);
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            P::wipe(&mut pixels);
+            return Err(e);
+        }
+        Ok(Png::new(self.height, self.width, pixels).with_physical_dimensions(self.physical))
+    }
+
+    /// Decodes only the first Adam7 pass of an interlaced image, giving a
+    /// preview at 1/8 of the width and height. Only the start of the image
+    /// data has to be read and decompressed.
+    ///
+    /// Fails if the image isn't interlaced.
+    pub fn preview(mut self) -> Result<Png, io::Error> {
+        if self.interlace != Interlace::Adam7 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Preview requires an Adam7 interlaced image",
+            ));
+        }
+
+        let (width, height) = ADAM7_PASSES[0].size(self.width, self.height);
+        let pixels = self.read_pass(width, height)?;
+        Ok(Png::new(height, width, pixels))
+    }
+
+    /// Decodes each of the seven Adam7 passes of an interlaced image as a
+    /// separate reduced image, in the order they're stored. Passes with no
+    /// pixels, which small images have, give empty images. See
+    /// [`ADAM7_PASSES`] for where each pass's pixels sit in the full image.
+    ///
+    /// Fails if the image isn't interlaced.
+    pub fn passes(mut self) -> Result<Vec<Png>, io::Error> {
+        if self.interlace != Interlace::Adam7 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Passes require an Adam7 interlaced image",
+            ));
+        }
+
+        iter::from_fn(|| self.next_pass()).collect()
+    }
+
+    /// Fails for interlaced images, which paths reading the scanlines top to
+    /// bottom can't handle yet
+    fn require_sequential(&self) -> io::Result<()> {
+        match self.interlace {
+            Interlace::None => Ok(()),
+            Interlace::Adam7 => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Interlaced images are not supported yet",
+            )),
+        }
+    }
+
+    /// Number of passes the image data is stored in
+    fn pass_count(&self) -> usize {
+        match self.interlace {
+            Interlace::None => 1,
+            Interlace::Adam7 => ADAM7_PASSES.len(),
+        }
+    }
+
+    /// Decodes the next pass as its own image: the whole image if it isn't
+    /// interlaced, otherwise the next Adam7 reduced image. Returns `None`
+    /// once every pass has been read, or after an error.
+    fn next_pass(&mut self) -> Option<io::Result<Png>> {
+        let (width, height) = match self.interlace {
+            _ if self.pass >= self.pass_count() => return None,
+            Interlace::None => (self.width, self.height),
+            Interlace::Adam7 => ADAM7_PASSES[self.pass].size(self.width, self.height),
+        };
+        self.pass += 1;
+
+        let result = self
+            .read_pass(width, height)
+            .map(|pixels| Png::new(height, width, pixels));
+        match result {
+            Ok(png) if self.interlace == Interlace::None => {
+                Some(Ok(png.with_physical_dimensions(self.physical)))
+            }
+            Ok(png) => Some(Ok(png)),
+            Err(e) => {
+                self.pass = self.pass_count();
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Decodes the image straight to 16 bit luminance values, in row-major
+    /// order. Alpha is ignored. This skips building a full [`Color`] for every
+    /// pixel, using a quarter of the memory of [`PngParser::parse`].
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] for interlaced images.
+    pub fn parse_luma(mut self) -> Result<Vec<u16>, io::Error> {
+        self.require_sequential()?;
+        let width = self.width as usize;
+        let mut luma = Vec::new();
+        self.read_scanlines(self.width, self.height, |color, data| {
+            let mut line = color
+                .parse_luma(data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            luma.extend_from_slice(&line[..width]);
+            crate::wipe(&mut line);
+            Ok(())
+        })?;
+        Ok(luma)
+    }
+
+    /// Decodes the image at a reduced resolution. Each output pixel is the
+    /// average of a `factor` x `factor` block of source pixels (smaller at the
+    /// right and bottom edges). Rows are averaged as they are read, so the
+    /// full size image is never held in memory.
+    pub fn parse_scaled(self, scale: Scale) -> Result<Png, io::Error> {
+        self.parse_scaled_with(scale, ResizeOptions::default())
+    }
+
+    /// Like [`PngParser::parse_scaled`], with control over how pixels are
+    /// averaged
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] for interlaced images.
+    pub fn parse_scaled_with(
+        mut self,
+        scale: Scale,
+        options: ResizeOptions,
+    ) -> Result<Png, io::Error> {
+        self.require_sequential()?;
+        let factor = scale.factor();
+        let width = self.width.div_ceil(factor);
+        let height = self.height.div_ceil(factor);
+
+        let mut pixels = Vec::new();
+        // Channel sums, total color weight and pixel count for each output pixel
+        // in the current row. Sized once the first row has been read
+        let mut sums = Vec::new();
+        let linear = options.linear_light;
+        let to_sum = |c: u16| {
+            if linear {
+                crate::srgb_to_linear(c)
+            } else {
+                c as f32
+            }
+        };
+        let from_sum = |c: f32| {
+            if linear {
+                crate::linear_to_srgb(c)
+            } else {
+                c.round() as u16
+            }
+        };
+        let mut row = 0;
+        let src_width = self.width as usize;
+        let src_height = self.height;
+        self.read_scanlines(self.width, self.height, |color, data| {
+            let mut line = color
+                .parse(data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            sums.resize(width as usize, ([0f32; 4], 0f32, 0u32));
+            for (x, c) in line[..src_width].iter().enumerate() {
+                let (sum, weight, count) = &mut sums[x / factor as usize];
+                let w = if options.premultiply_alpha {
+                    c.alpha() as f32 / u16::MAX as f32
+                } else {
+                    1.0
+                };
+                sum[0] += to_sum(c.red()) * w;
+                sum[1] += to_sum(c.green()) * w;
+                sum[2] += to_sum(c.blue()) * w;
+                sum[3] += c.alpha() as f32;
+                *weight += w;
+                *count += 1;
+            }
+            crate::wipe(&mut line);
+
+            row += 1;
+            if row % factor == 0 || row == src_height {
+                for (sum, weight, count) in sums.iter_mut() {
+                    // Fully transparent blocks have no color to keep
+                    let [r, g, b] = if *weight > 0.0 {
+                        [sum[0], sum[1], sum[2]].map(|s| from_sum(s / *weight))
+                    } else {
+                        [0; 3]
+                    };
+                    let a = (sum[3] / *count as f32).round() as u16;
+                    pixels.push(Color::new(r, g, b, a));
+                    *sum = [0.0; 4];
+                    *weight = 0.0;
+                    *count = 0;
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(Png::new(height, width, pixels))
+    }
+
+    /// Reads and de-filters the scanlines of a single (reduced) image
+    fn read_pass(&mut self, width: u32, height: u32) -> Result<Vec<Color>, io::Error> {
+        // Grown as scanlines are read rather than sized from the header, so a
+        // header claiming a huge image fails once the data runs out
         let mut pixels: Vec<Color> = Vec::new();
+        let result = self.read_scanlines(width, height, |color, data| {
+            let mut line = color
+                .parse(data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            pixels.extend_from_slice(&line[..width as usize]);
+            crate::wipe(&mut line);
+            Ok(())
+        });
+        if let Err(e) = result {
+            crate::wipe(&mut pixels);
+            return Err(e);
+        }
+        Ok(pixels)
+    }
 
-        // TODO: change for interlace method and pass #
-        let mut prev = vec![0; self.scanline_length()];
-        let mut line = vec![0; self.scanline_length()];
+    /// Reads and de-filters `height` scanlines of `width` pixels, handing the
+    /// data of each (without the filter type byte) to `f`
+    fn read_scanlines(
+        &mut self,
+        width: u32,
+        height: u32,
+        mut f: impl FnMut(&PngColor, &[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.check_size()?;
+        // Empty passes have no scanlines, not even filter type bytes
+        if width == 0 {
+            return Ok(());
+        }
+
+        // Scanlines are read without filling the buffers first, so a width in
+        // the header that the data doesn't back up fails once the data runs
+        // out. Reserving exactly means the buffer is never reallocated,
+        // leaving unwiped copies behind
+        let length = self.scanline_length(width);
+        let mut line = Vec::new();
+        line.try_reserve_exact(length)
+            .map_err(|_| io::Error::new(ErrorKind::OutOfMemory, "Scanline too long to decode"))?;
+        let mut prev = Vec::new();
+        let bpp = self.color.data_len().div_ceil(8);
 
-        for _ in 0..self.height {
-            self.reader.read_exact(&mut line)?;
-            dbg!(&line);
-            let (filter_kind, data) = line
-                .split_first()
+        let result = (0..height).try_for_each(|_| {
+            line.clear();
+            (&mut self.reader)
+                .take(length as u64)
+                .read_to_end(&mut line)?;
+            if line.len() != length {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof));
+            }
+            prev.resize(length, 0);
+            let (filter_type, data) = line
+                .split_first_mut()
                 .expect("Line must be self.scanline_length()");
-            let filter_kind = FilterKind::try_from(*filter_kind)
+            self.filter
+                .unfilter(*filter_type, data, &prev[1..], bpp)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-            assert_eq!(filter_kind, FilterKind::default()); // TODO: replace with filtering
 
-            pixels.extend_from_slice(&self.color.parse(data).unwrap()[..self.width as usize]);
+            f(&self.color, data)?;
 
             std::mem::swap(&mut prev, &mut line);
-        }
-        dbg!(pixels);
+            Ok(())
+        });
 
-        // De-interlace
-        // Could also be done after converting bytes to colors
-        //  - makes sense when using progressive parser
+        // Wiped on errors too, as they may be partly decoded
+        crate::wipe(&mut prev);
+        crate::wipe(&mut line);
+        result
+    }
+}
 
-        // Convert bytes to colors
+/// Progress of a decode running on another thread. See [`spawn_decode`]
+#[derive(Debug)]
+pub enum RowEvent {
+    /// The header was read. Always the first event unless decoding fails
+    /// before then
+    Header { width: u32, height: u32 },
+    /// A decoded row, sent top to bottom
+    Row { y: u32, pixels: Vec<Color> },
+    /// Decoding stopped, either after the last row or because of an error.
+    /// Always the last event
+    Finished(Result<(), io::Error>),
+}
 
-        todo!()
-    }
+/// Decodes a png on a new thread, sending the header and each row over a
+/// channel as they are decoded. Decoding stops early if the receiver is
+/// dropped. Interlaced images aren't supported yet, and finish with an
+/// [`io::ErrorKind::Unsupported`] error.
+pub fn spawn_decode<R>(reader: R) -> (JoinHandle<()>, Receiver<RowEvent>)
+where
+    R: Read + Seek + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let disconnected = || io::Error::other("Row event receiver was dropped");
+        let result = PngParser::new(reader).and_then(|mut parser| {
+            parser.require_sequential()?;
+            let (width, height) = (parser.width, parser.height);
+            sender
+                .send(RowEvent::Header { width, height })
+                .map_err(|_| disconnected())?;
+
+            let mut y = 0;
+            parser.read_scanlines(width, height, |color, data| {
+                let mut pixels = color
+                    .parse(data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                pixels.truncate(width as usize);
+                sender
+                    .send(RowEvent::Row { y, pixels })
+                    .map_err(|_| disconnected())?;
+                y += 1;
+                Ok(())
+            })
+        });
+        // Nobody to tell if the receiver is gone
+        _ = sender.send(RowEvent::Finished(result));
+    });
+    (handle, receiver)
 }
 
+/// Decodes a png held in memory. See [`PngParser::from_bytes`]
+pub fn decode(bytes: &[u8]) -> io::Result<Png> {
+    PngParser::from_bytes(bytes)?.parse()
+}
+
+/// Yields the image one pass at a time: the whole image if it isn't
+/// interlaced, otherwise each Adam7 reduced image. Iteration stops at the
+/// first error; use [`PngParser::passes`] to see it.
 impl<R> Iterator for PngParser<R>
 where
     R: Read,
@@ -165,15 +912,12 @@ where
     type Item = Png;
 
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        self.next_pass()?.ok()
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        match self.interlace_method {
-            0 => (1, Some(1)),
-            1 => (7, Some(7)),
-            _ => (0, Some(0)),
-        }
+        let remaining = self.pass_count() - self.pass;
+        (remaining, Some(remaining))
     }
 }
 
@@ -186,27 +930,222 @@ mod tests {
     use crate::Color;
 
     use super::*;
+    use crate::test_util::{tiny_png_with, TINY_PNG};
+
+    const INTERLACED_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x00, 0x00, 0x00, 0x01, 0x20,
+        0xda, 0x62, 0x6e, 0x00, 0x00, 0x00, 0x0f, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x10,
+        0x60, 0x50, 0x60, 0x30, 0x70, 0x00, 0x00, 0x01, 0x87, 0x00, 0xa1, 0xa6, 0x3f, 0xd1, 0xd0,
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    const RGB_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x7b,
+        0x40, 0xe8, 0xdd, 0x00, 0x00, 0x00, 0x0f, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8,
+        0xcf, 0xc0, 0xf0, 0xff, 0xff, 0x7f, 0x00, 0x0b, 0xfb, 0x03, 0xfd, 0xdd, 0x17, 0x6f, 0x47,
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    const GREY_3X3_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x08, 0x00, 0x00, 0x00, 0x00, 0x73,
+        0x43, 0xea, 0x63, 0x00, 0x00, 0x00, 0x14, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x10, 0xd1, 0x60, 0xb0, 0x09, 0x48, 0x61, 0xa8, 0xe8, 0x59, 0x00, 0x00, 0x0a, 0xd4, 0x02,
+        0xd1, 0x02, 0xc2, 0xa5, 0x12, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42,
+        0x60, 0x82,
+    ];
 
-    const TINY_PNG: &[u8] = &[
+    const BLACK_WHITE_PNG: &[u8] = &[
         0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
-        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x37,
-        0x6e, 0xf9, 0x24, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x63, 0x60,
-        0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x73, 0x75, 0x01, 0x18, 0x00, 0x00, 0x00, 0x00, 0x49,
-        0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0xd1,
+        0x49, 0x20, 0x56, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0xf8, 0x0f, 0x00, 0x01, 0x02, 0x01, 0x00, 0x42, 0xbe, 0xbc, 0x68, 0x00, 0x00, 0x00, 0x00,
+        0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
     ];
 
-    // #[test]
-    // fn test_tiny() {
-    //     let mut parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
-    //     let image = parser.next().unwrap();
-    //     assert_eq!(parser.next(), None);
+    const HALO_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0xf4,
+        0x22, 0x7f, 0x8a, 0x00, 0x00, 0x00, 0x0e, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8,
+        0x0f, 0x04, 0x0c, 0x40, 0x00, 0x00, 0x19, 0xef, 0x03, 0xfd, 0x6c, 0xc8, 0x94, 0x08, 0x00,
+        0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
 
-    //     let mut pixels = image.pixels();
-    //     let pixel = pixels.next().unwrap();
+    const SPLIT_IDAT_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x08, 0x00, 0x00, 0x00, 0x00, 0x73,
+        0x43, 0xea, 0x63, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x10, 0xd1, 0x60, 0x08, 0x48, 0xa9, 0x29, 0xf0, 0x6c, 0x01, 0x00, 0x00, 0x00, 0x0a, 0x49,
+        0x44, 0x41, 0x54, 0x60, 0x58, 0xb0, 0xe5, 0x04, 0x00, 0x0d, 0x2c, 0x03, 0x85, 0x51, 0x91,
+        0x66, 0x58, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
 
-    //     assert_eq!(*pixel, Color::new_opaque(0, 0, 0));
-    //     assert_eq!(pixels.next(), None);
-    // }
+    /// 3x5 truecolor, with rows filtered by None, Sub, Up, Average and
+    /// Paeth in turn
+    const FILTERED_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x05, 0x08, 0x02, 0x00, 0x00, 0x00, 0x0f,
+        0x13, 0xc1, 0xf5, 0x00, 0x00, 0x00, 0x3d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x01, 0x32,
+        0x00, 0xcd, 0xff, 0x00, 0xa5, 0x4d, 0xca, 0x18, 0x25, 0x30, 0xbb, 0x1d, 0x6d, 0x01, 0x13,
+        0x2c, 0xde, 0xc3, 0xf7, 0x9d, 0x58, 0xb6, 0xa3, 0x02, 0x2c, 0x46, 0x41, 0xf5, 0xf6, 0xf6,
+        0xe9, 0x6b, 0x76, 0x03, 0xb7, 0x10, 0x2d, 0xcd, 0x2b, 0xde, 0x06, 0x6e, 0xcd, 0x04, 0x4a,
+        0xd5, 0x2d, 0xde, 0xbc, 0x37, 0x51, 0x0e, 0x19, 0x22, 0x5e, 0x15, 0x9c, 0x33, 0xa1, 0x3d,
+        0x3d, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    /// Header claiming the largest allowed 16 bit RGBA image, followed by a
+    /// few bytes of image data
+    const HUGE_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x7f, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 0x10, 0x06, 0x00, 0x00, 0x00, 0x44,
+        0x59, 0xd7, 0x25, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x40, 0x05, 0x00, 0x00, 0x10, 0x00, 0x01, 0x39, 0xbd, 0x8f, 0x65, 0x00, 0x00, 0x00, 0x00,
+        0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    /// Interlaced 16384x16384 grey header, at the default pixel limit,
+    /// followed by a few bytes of image data
+    const HUGE_INTERLACED_PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x40, 0x00, 0x08, 0x00, 0x00, 0x00, 0x01, 0xfb,
+        0xa4, 0x7f, 0xce, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60,
+        0x40, 0x05, 0x00, 0x00, 0x10, 0x00, 0x01, 0x39, 0xbd, 0x8f, 0x65, 0x00, 0x00, 0x00, 0x00,
+        0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn test_tiny() {
+        let mut parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
+        assert_eq!(parser.len(), 1);
+        let image = parser.next().unwrap();
+        assert_eq!(parser.next(), None);
+        assert_eq!(parser.len(), 0);
+
+        let mut pixels = image.pixels();
+        let pixel = pixels.next().unwrap();
+
+        assert_eq!(*pixel, Color::new_opaque(0, 0, 0));
+        assert_eq!(pixels.next(), None);
+    }
+
+    #[test]
+    fn test_passes() {
+        let parser = PngParser::new(Cursor::new(INTERLACED_PNG)).unwrap();
+        assert_eq!(parser.len(), 7);
+        let passes = parser.passes().unwrap();
+
+        let sizes: Vec<_> = passes.iter().map(|p| (p.width(), p.height())).collect();
+        assert_eq!(
+            sizes,
+            [(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (1, 1), (2, 1)]
+        );
+        let grey = |v: u16| Color::new_opaque(v, v, v);
+        assert_eq!(
+            passes[0].pixels().copied().collect::<Vec<_>>(),
+            [grey(0x1010)]
+        );
+        assert_eq!(
+            passes[5].pixels().copied().collect::<Vec<_>>(),
+            [grey(0x2020)]
+        );
+        assert_eq!(
+            passes[6].pixels().copied().collect::<Vec<_>>(),
+            [grey(0x3030), grey(0x4040)]
+        );
+
+        let parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
+        assert!(parser.passes().is_err());
+    }
+
+    #[test]
+    fn test_header_accessors() {
+        let parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
+        assert_eq!(parser.width(), 1);
+        assert_eq!(parser.height(), 1);
+        assert_eq!(parser.color_kind(), ColorKind::Grey(false));
+        assert_eq!(parser.bit_depth(), 1);
+        assert_eq!(parser.interlace(), Interlace::None);
+    }
+
+    #[test]
+    fn test_header_dimensions() {
+        let header = |width: u32, height: u32| {
+            let mut data = width.to_be_bytes().to_vec();
+            data.extend_from_slice(&height.to_be_bytes());
+            data.extend_from_slice(&[8, 0, 0, 0, 0]);
+            Chunk::new(intermediate::IHDR, data.into())
+        };
+        let from_header = |header| PngParser::from_header(&header, &[][..], Vec::new(), None);
+        assert!(from_header(header(1, MAX_DIMENSION)).is_ok());
+        for (width, height) in [(0, 1), (1, 0), (MAX_DIMENSION + 1, 1), (1, u32::MAX)] {
+            let e = from_header(header(width, height)).err().unwrap();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+
+        // Dimensions the data doesn't back up fail cleanly, either once it
+        // runs out or when a scanline can't be allocated
+        let failed =
+            |e: io::Error| matches!(e.kind(), ErrorKind::UnexpectedEof | ErrorKind::OutOfMemory);
+        let parser = PngParser::new(Cursor::new(HUGE_PNG)).unwrap();
+        assert!(failed(parser.max_pixels(None).parse().unwrap_err()));
+    }
+
+    #[test]
+    fn test_max_pixels() {
+        let parser = PngParser::new(Cursor::new(HUGE_PNG)).unwrap();
+        let e = parser.parse().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(decode(HUGE_PNG).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // The full image isn't allocated until the passes have been read
+        let parser = PngParser::new(Cursor::new(HUGE_INTERLACED_PNG)).unwrap();
+        let e = parser.parse().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        let parser = PngParser::new(Cursor::new(HUGE_INTERLACED_PNG)).unwrap();
+        let e = parser.max_pixels(Some(1 << 20)).parse().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        let parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
+        assert!(parser.max_pixels(Some(0)).parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_interlaced() {
+        let parser = PngParser::new(Cursor::new(INTERLACED_PNG)).unwrap();
+        let grey = |v: u16| Color::new_opaque(v, v, v);
+        assert_eq!(
+            parser.parse().unwrap().into_pixels(),
+            [grey(0x1010), grey(0x2020), grey(0x3030), grey(0x4040)]
+        );
+    }
+
+    #[test]
+    fn test_parse_as() {
+        use crate::{Luma16, Rgba8};
+
+        let parser = PngParser::new(Cursor::new(GREY_3X3_PNG)).unwrap();
+        let luma = parser.parse_as::<Luma16>().unwrap();
+        let parser = PngParser::new(Cursor::new(GREY_3X3_PNG)).unwrap();
+        assert_eq!(luma, parser.parse().unwrap().convert());
+
+        let parser = PngParser::new(Cursor::new(INTERLACED_PNG)).unwrap();
+        let rgba = parser.parse_as::<Rgba8>().unwrap();
+        assert_eq!(rgba.into_pixels()[3], Rgba8([0x40, 0x40, 0x40, 0xff]));
+
+        // Scanlines of 8 bits or less are read through the 8 bit tables
+        for png in [TINY_PNG, RGB_PNG, GREY_3X3_PNG] {
+            let expected = PngParser::new(Cursor::new(png)).unwrap().parse().unwrap();
+            let parser = PngParser::new(Cursor::new(png)).unwrap();
+            assert_eq!(parser.parse_as::<Rgba8>().unwrap(), expected.convert());
+            let parser = PngParser::new(Cursor::new(png)).unwrap();
+            assert_eq!(parser.parse_as::<Color>().unwrap(), expected);
+        }
+
+        let parser = PngParser::new(Cursor::new(HUGE_PNG)).unwrap();
+        assert!(parser.parse_as::<Rgba8>().is_err());
+    }
 
     #[test]
     fn test_parse_tiny() {
@@ -219,4 +1158,352 @@ mod tests {
         assert_eq!(*pixel, Color::new_opaque(0, 0, 0));
         assert_eq!(pixels.next(), None);
     }
+
+    #[test]
+    fn test_parse_filtered() {
+        let image = PngParser::new(Cursor::new(FILTERED_PNG))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let rows: [[(u8, u8, u8); 3]; 5] = [
+            [(165, 77, 202), (24, 37, 48), (187, 29, 109)],
+            [(19, 44, 222), (214, 35, 123), (46, 217, 30)],
+            [(63, 114, 31), (203, 25, 113), (23, 68, 148)],
+            [(214, 73, 60), (157, 92, 52), (96, 190, 49)],
+            [(32, 30, 105), (254, 218, 160), (238, 232, 185)],
+        ];
+        let expected: Vec<_> = rows
+            .iter()
+            .flatten()
+            .map(|&(r, g, b)| Color::new_opaque(r as u16 * 257, g as u16 * 257, b as u16 * 257))
+            .collect();
+        assert_eq!(image.into_pixels(), expected);
+    }
+
+    #[test]
+    fn test_preview() {
+        let parser = PngParser::new(Cursor::new(INTERLACED_PNG)).unwrap();
+        let preview = parser.preview().unwrap();
+
+        assert_eq!((preview.width(), preview.height()), (1, 1));
+        let grey = 0x1010;
+        assert_eq!(
+            preview.into_pixels(),
+            vec![Color::new_opaque(grey, grey, grey)]
+        );
+
+        let parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
+        assert!(parser.preview().is_err());
+    }
+
+    #[test]
+    fn test_parse_luma() {
+        let parser = PngParser::new(Cursor::new(TINY_PNG)).unwrap();
+        assert_eq!(parser.parse_luma().unwrap(), vec![0]);
+
+        let parser = PngParser::new(Cursor::new(RGB_PNG)).unwrap();
+        assert_eq!(parser.parse_luma().unwrap(), vec![13932, u16::MAX]);
+
+        let parser = PngParser::new(Cursor::new(INTERLACED_PNG)).unwrap();
+        let error = parser.parse_luma().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+
+        let parser = PngParser::new(Cursor::new(HUGE_PNG)).unwrap();
+        assert!(parser.parse_luma().is_err());
+    }
+
+    #[test]
+    fn test_parse_scaled() {
+        let grey = |v: u16| Color::new_opaque(v * 257, v * 257, v * 257);
+
+        let parser = PngParser::new(Cursor::new(GREY_3X3_PNG)).unwrap();
+        let image = parser.parse_scaled(Scale::Half).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(
+            image.into_pixels(),
+            vec![grey(40), grey(70), grey(130), grey(160)]
+        );
+
+        let parser = PngParser::new(Cursor::new(GREY_3X3_PNG)).unwrap();
+        let image = parser.parse_scaled(Scale::Eighth).unwrap();
+        assert_eq!(image.into_pixels(), vec![grey(80)]);
+
+        let parser = PngParser::new(Cursor::new(INTERLACED_PNG)).unwrap();
+        let error = parser.parse_scaled(Scale::Half).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+
+        let parser = PngParser::new(Cursor::new(HUGE_PNG)).unwrap();
+        assert!(parser.parse_scaled(Scale::Half).is_err());
+    }
+
+    #[test]
+    fn test_chunk_filter() {
+        let text = Chunk::new(intermediate::TEXT, b"Title\0Tiny".to_vec().into());
+        let gamma = Chunk::new(intermediate::GAMA, 45455u32.to_be_bytes().into());
+        let tagged = tiny_png_with([text.clone(), gamma.clone()]);
+
+        let parser = PngParser::new(Cursor::new(&tagged)).unwrap();
+        assert!(parser.chunks().is_empty());
+        assert_eq!(parser.parse().unwrap().pixels().len(), 1);
+
+        assert!(PngParser::from_bytes(&tagged).unwrap().chunks().is_empty());
+
+        let filter = ChunkFilter::new().keep(intermediate::TEXT);
+        let parser = PngParser::with_chunk_filter(Cursor::new(&tagged), filter.clone()).unwrap();
+        assert_eq!(parser.chunks(), std::slice::from_ref(&text));
+        let parser = PngParser::from_bytes_with_chunk_filter(&tagged, filter).unwrap();
+        assert_eq!(parser.chunks(), [text]);
+
+        let filter = ChunkFilter::new()
+            .default_action(ChunkAction::Keep)
+            .drop(intermediate::TEXT);
+        let parser = PngParser::with_chunk_filter(Cursor::new(&tagged), filter.clone()).unwrap();
+        assert_eq!(parser.chunks(), std::slice::from_ref(&gamma));
+        let parser = PngParser::from_bytes_with_chunk_filter(&tagged, filter).unwrap();
+        assert_eq!(parser.chunks(), [gamma]);
+        assert_eq!(parser.parse().unwrap().pixels().len(), 1);
+    }
+
+    #[test]
+    fn test_resync() {
+        let text = Chunk::new(intermediate::TEXT, b"Title\0Tiny".to_vec().into());
+        let tagged = tiny_png_with([text]);
+
+        let mut bad_crc = tagged.clone();
+        bad_crc[51] ^= 0xff;
+        let mut bad_length = tagged.clone();
+        bad_length[34] = 0x10;
+
+        for corrupt in [bad_crc, bad_length] {
+            let filter = ChunkFilter::new().keep(intermediate::TEXT);
+            assert!(PngParser::with_chunk_filter(Cursor::new(&corrupt), filter.clone()).is_err());
+
+            assert!(PngParser::from_bytes_with_chunk_filter(&corrupt, filter.clone()).is_err());
+
+            let filter = filter.recovery(Recovery::Resync);
+            let parser =
+                PngParser::with_chunk_filter(Cursor::new(&corrupt), filter.clone()).unwrap();
+            assert!(parser.chunks().is_empty());
+            assert_eq!(parser.parse().unwrap().pixels().len(), 1);
+            let parser = PngParser::from_bytes_with_chunk_filter(&corrupt, filter).unwrap();
+            assert!(parser.chunks().is_empty());
+            assert_eq!(parser.parse().unwrap().pixels().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        for png in [TINY_PNG, RGB_PNG, GREY_3X3_PNG, HALO_PNG] {
+            let expected = PngParser::new(Cursor::new(png)).unwrap().parse().unwrap();
+            assert_eq!(decode(png).unwrap(), expected);
+        }
+
+        let phys = Chunk::new(
+            intermediate::PHYS,
+            vec![0, 0, 0x0b, 0x13, 0, 0, 0x0b, 0x13, 1].into(),
+        );
+        let tagged = tiny_png_with([phys]);
+        let parser = PngParser::from_bytes(&tagged).unwrap();
+        assert_eq!(parser.physical_dimensions().unwrap().x, 0x0b13);
+
+        assert!(decode(&TINY_PNG[..40]).is_err());
+        let mut corrupt = TINY_PNG.to_vec();
+        corrupt[50] ^= 0xff; // IDAT crc
+        assert!(decode(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_non_consecutive_idat() {
+        use intermediate::rewriter::Rewriter;
+
+        let expected = decode(SPLIT_IDAT_PNG).unwrap();
+        let text = Chunk::new(intermediate::TEXT, b"Title\0Split".to_vec().into());
+        let mut split = Vec::new();
+        Rewriter::new()
+            .insert_after(intermediate::IDAT, text)
+            .rewrite(SPLIT_IDAT_PNG, &mut split)
+            .unwrap();
+
+        let is_non_consecutive = |e: io::Error| {
+            matches!(
+                e.get_ref().and_then(|e| e.downcast_ref()),
+                Some(crate::PngError::NonConsecutiveIdat)
+            )
+        };
+        let parser = PngParser::new(Cursor::new(&split)).unwrap();
+        assert!(is_non_consecutive(parser.parse().unwrap_err()));
+        assert!(is_non_consecutive(decode(&split).unwrap_err()));
+
+        let filter = ChunkFilter::new().lenient_idat(true);
+        let parser = PngParser::with_chunk_filter(Cursor::new(&split), filter.clone()).unwrap();
+        assert_eq!(parser.parse().unwrap(), expected);
+        let parser = PngParser::from_bytes_with_chunk_filter(&split, filter).unwrap();
+        assert_eq!(parser.parse().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_xmp() {
+        let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+        let tagged = tiny_png_with([xmp::xmp_chunk(packet).unwrap()]);
+
+        let parser = PngParser::new(Cursor::new(&tagged)).unwrap();
+        assert_eq!(parser.xmp(), None);
+
+        let filter = ChunkFilter::new().keep(intermediate::ITXT);
+        let parser = PngParser::with_chunk_filter(Cursor::new(&tagged), filter).unwrap();
+        assert_eq!(parser.xmp().unwrap(), packet);
+    }
+
+    #[test]
+    fn test_exif() {
+        // Big endian TIFF header and a directory with only Make = "Ab"
+        let exif = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x0f\0\x02\0\0\0\x03Ab\0\0\0\0\0\0";
+        let tagged = tiny_png_with([Chunk::new(intermediate::EXIF, exif.to_vec().into())]);
+
+        let parser = PngParser::new(Cursor::new(&tagged)).unwrap();
+        assert_eq!(parser.exif(), None);
+
+        let filter = ChunkFilter::new().keep(intermediate::EXIF);
+        let parser = PngParser::with_chunk_filter(Cursor::new(&tagged), filter).unwrap();
+        assert_eq!(parser.exif().unwrap().make(), Some("Ab"));
+    }
+
+    #[test]
+    fn test_with_filter() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use intermediate::filter::Adaptive;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counting;
+        impl Filter for Counting {
+            fn unfilter(
+                &self,
+                filter_type: u8,
+                line: &mut [u8],
+                prev: &[u8],
+                bpp: usize,
+            ) -> Result<(), &'static str> {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                Adaptive.unfilter(filter_type, line, prev, bpp)
+            }
+        }
+
+        let parser = PngParser::new(Cursor::new(GREY_3X3_PNG))
+            .unwrap()
+            .with_filter(Counting);
+        parser.parse().unwrap();
+        assert_eq!(CALLS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_spawn_decode() {
+        let (handle, events) = spawn_decode(Cursor::new(GREY_3X3_PNG));
+        let events: Vec<RowEvent> = events.iter().collect();
+        handle.join().unwrap();
+
+        assert_eq!(events.len(), 5);
+        assert!(matches!(
+            events[0],
+            RowEvent::Header {
+                width: 3,
+                height: 3
+            }
+        ));
+        for (y, event) in events[1..4].iter().enumerate() {
+            let RowEvent::Row { y: row, pixels } = event else {
+                panic!("Expected row, got {event:?}");
+            };
+            assert_eq!(*row, y as u32);
+            assert_eq!(pixels.len(), 3);
+        }
+        assert!(matches!(events[4], RowEvent::Finished(Ok(()))));
+
+        let (handle, events) = spawn_decode(Cursor::new(&TINY_PNG[..20]));
+        let events: Vec<RowEvent> = events.iter().collect();
+        handle.join().unwrap();
+        assert!(matches!(events[..], [RowEvent::Finished(Err(_))]));
+
+        let (handle, events) = spawn_decode(Cursor::new(INTERLACED_PNG));
+        let events: Vec<RowEvent> = events.iter().collect();
+        handle.join().unwrap();
+        assert!(matches!(
+            &events[..],
+            [RowEvent::Finished(Err(e))] if e.kind() == io::ErrorKind::Unsupported
+        ));
+    }
+
+    #[test]
+    fn test_physical_dimensions() {
+        use intermediate::Unit;
+
+        let phys = Chunk::new(
+            intermediate::PHYS,
+            vec![0, 0, 0x0b, 0x13, 0, 0, 0x0b, 0x13, 1].into(),
+        );
+        let tagged = tiny_png_with([phys]);
+
+        let parser = PngParser::new(Cursor::new(&tagged)).unwrap();
+        let expected = PhysicalDimensions {
+            x: 2835,
+            y: 2835,
+            unit: Unit::Meter,
+        };
+        assert_eq!(parser.physical_dimensions(), Some(expected));
+        assert!(parser.chunks().is_empty());
+
+        let image = parser.parse().unwrap();
+        assert_eq!(image.physical_dimensions(), Some(expected));
+
+        // All zero, which the spec doesn't allow, is ignored rather than fatal
+        let zeroed = Chunk::new(intermediate::PHYS, vec![0; 9].into());
+        let tagged = tiny_png_with([zeroed]);
+        let parser = PngParser::new(Cursor::new(&tagged)).unwrap();
+        assert_eq!(parser.physical_dimensions(), None);
+        let image = decode(&tagged).unwrap();
+        assert_eq!(image.physical_dimensions(), None);
+    }
+
+    #[test]
+    fn test_linear_light_scaling() {
+        let parser = PngParser::new(Cursor::new(BLACK_WHITE_PNG)).unwrap();
+        let pixels = parser.parse_scaled(Scale::Half).unwrap().into_pixels();
+        assert_eq!(pixels, vec![Color::new_opaque(0x8000, 0x8000, 0x8000)]);
+
+        let options = ResizeOptions {
+            linear_light: true,
+            ..Default::default()
+        };
+        let parser = PngParser::new(Cursor::new(BLACK_WHITE_PNG)).unwrap();
+        let pixels = parser
+            .parse_scaled_with(Scale::Half, options)
+            .unwrap()
+            .into_pixels();
+        let grey = pixels[0].red();
+        assert!((48150..48250).contains(&grey), "{grey:X}");
+        assert_eq!(pixels[0].alpha(), u16::MAX);
+    }
+
+    #[test]
+    fn test_premultiplied_scaling() {
+        // Opaque white next to transparent black
+        let parser = PngParser::new(Cursor::new(HALO_PNG)).unwrap();
+        let pixels = parser.parse_scaled(Scale::Half).unwrap().into_pixels();
+        assert_eq!(pixels, vec![Color::new(0x8000, 0x8000, 0x8000, 0x8000)]);
+
+        let options = ResizeOptions {
+            premultiply_alpha: true,
+            ..Default::default()
+        };
+        let parser = PngParser::new(Cursor::new(HALO_PNG)).unwrap();
+        let pixels = parser
+            .parse_scaled_with(Scale::Half, options)
+            .unwrap()
+            .into_pixels();
+        assert_eq!(
+            pixels,
+            vec![Color::new(u16::MAX, u16::MAX, u16::MAX, 0x8000)]
+        );
+    }
 }
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::Color;
+
+/// A format for storing the pixels of a [`Png`](crate::Png). Decoding gives
+/// 16 bit rgba [`Color`]s unless another format is asked for with
+/// [`PngParser::parse_as`](crate::parser::PngParser::parse_as), which saves
+/// memory when the source doesn't need the full precision. Images can be
+/// switched between formats with [`Png::convert`](crate::Png::convert).
+pub trait Pixel: Copy + PartialEq + fmt::Debug {
+    fn to_color(self) -> Color;
+
+    /// Converts from 16 bit rgba, losing whatever the format can't hold
+    fn from_color(color: Color) -> Self;
+
+    /// Converts from 8 bit rgba, which decoding uses for images of 8 bits or
+    /// less. Defaults to going through [`Pixel::from_color`]
+    fn from_rgba8(rgba: [u8; 4]) -> Self {
+        Self::from_color(Rgba8(rgba).to_color())
+    }
+
+    /// Securely wipes pixels, called when an image is dropped with the
+    /// `zeroize` feature enabled. Does nothing unless overridden; the pixel
+    /// types in this crate override it.
+    fn wipe(_pixels: &mut [Self]) {}
+}
+
+impl Pixel for Color {
+    fn to_color(self) -> Color {
+        self
+    }
+
+    fn from_color(color: Color) -> Self {
+        color
+    }
+
+    fn wipe(pixels: &mut [Self]) {
+        pixels.iter_mut().for_each(crate::wipe);
+    }
+}
+
+/// 8 bit representation of rgba color, using half the memory of [`Color`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgba8(pub [u8; 4]);
+
+impl Pixel for Rgba8 {
+    fn to_color(self) -> Color {
+        let [r, g, b, a] = self.0.map(|v| v as u16 * 257);
+        Color::new(r, g, b, a)
+    }
+
+    fn from_color(color: Color) -> Self {
+        let to_8 = |v: u16| ((v as u32 + 128) / 257) as u8;
+        Self([color.red(), color.green(), color.blue(), color.alpha()].map(to_8))
+    }
+
+    fn from_rgba8(rgba: [u8; 4]) -> Self {
+        Self(rgba)
+    }
+
+    fn wipe(pixels: &mut [Self]) {
+        pixels.iter_mut().for_each(crate::wipe);
+    }
+}
+
+/// 16 bit grey level without alpha, using a quarter of the memory of
+/// [`Color`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Luma16(pub u16);
+
+impl Pixel for Luma16 {
+    fn to_color(self) -> Color {
+        Color::new_opaque(self.0, self.0, self.0)
+    }
+
+    /// Grey colors keep their level exactly. Others are converted with
+    /// [`Color::luma`]. Alpha is dropped
+    fn from_color(color: Color) -> Self {
+        if color.red() == color.green() && color.green() == color.blue() {
+            Self(color.red())
+        } else {
+            Self(color.luma())
+        }
+    }
+
+    fn wipe(pixels: &mut [Self]) {
+        pixels.iter_mut().for_each(crate::wipe);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Rgba8 {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Luma16 {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgba8() {
+        let color = Color::new(0x1234, 0x0080, 0xff00, u16::MAX);
+        assert_eq!(Rgba8::from_color(color), Rgba8([0x12, 0x00, 0xfe, 0xff]));
+        assert!((0..=255u8).all(|v| Rgba8::from_color(Rgba8([v; 4]).to_color()) == Rgba8([v; 4])));
+    }
+
+    #[test]
+    fn test_custom_pixel() {
+        // Needs nothing beyond the trait, whichever features are enabled
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Red(u16);
+
+        impl Pixel for Red {
+            fn to_color(self) -> Color {
+                Color::new_opaque(self.0, 0, 0)
+            }
+
+            fn from_color(color: Color) -> Self {
+                Self(color.red())
+            }
+        }
+
+        let png = crate::Png::new(1, 1, vec![Color::new_opaque(7, 8, 9)]);
+        assert_eq!(png.convert::<Red>().into_pixels(), [Red(7)]);
+    }
+
+    #[test]
+    fn test_luma16() {
+        let grey = Color::new(0xfffe, 0xfffe, 0xfffe, 0);
+        assert_eq!(Luma16::from_color(grey), Luma16(0xfffe));
+        assert_eq!(
+            Luma16(0xfffe).to_color(),
+            Color::new_opaque(0xfffe, 0xfffe, 0xfffe)
+        );
+        assert_eq!(
+            Luma16::from_color(Color::new_opaque(u16::MAX, 0, 0)),
+            Luma16(13932)
+        );
+    }
+}
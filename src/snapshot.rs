@@ -0,0 +1,227 @@
+//! Compares decoded images against golden files, for visual regression
+//! tests. See [`assert_png_eq!`](crate::assert_png_eq).
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{parser::PngParser, Color, Png};
+
+/// Asserts that an image matches the png at a path, allowing each channel to
+/// differ by a tolerance (0 unless given). On failure the actual image and a
+/// diff are written next to the golden file. See [`check_snapshot`].
+///
+/// ```ignore
+/// assert_png_eq!(image, "tests/golden/logo.png");
+/// assert_png_eq!(image, "tests/golden/logo.png", 0x0101);
+/// ```
+#[macro_export]
+macro_rules! assert_png_eq {
+    ($actual:expr, $expected_path:expr) => {
+        $crate::assert_png_eq!($actual, $expected_path, 0)
+    };
+    ($actual:expr, $expected_path:expr, $tolerance:expr) => {
+        if let Err(message) = $crate::snapshot::check_snapshot(&$actual, $expected_path, $tolerance)
+        {
+            panic!("{}", message);
+        }
+    };
+}
+
+/// Pixels differing by more than the tolerance in some channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Difference {
+    /// Number of differing pixels
+    pub count: usize,
+    /// Position of the first differing pixel
+    pub first: (u32, u32),
+    /// Largest difference in any channel
+    pub max_delta: u16,
+}
+
+/// Compares two images of the same size, channel by channel. Returns `None`
+/// if no channel differs by more than `tolerance`.
+///
+/// # Panics
+/// Panics if the images aren't the same size.
+pub fn difference(actual: &Png, expected: &Png, tolerance: u16) -> Option<Difference> {
+    assert_eq!(
+        (actual.width(), actual.height()),
+        (expected.width(), expected.height()),
+        "Images must be the same size"
+    );
+    let width = actual.width() as usize;
+    let mut diff: Option<Difference> = None;
+    for (i, (a, e)) in actual.pixels().zip(expected.pixels()).enumerate() {
+        let delta = max_delta(*a, *e);
+        if delta <= tolerance {
+            continue;
+        }
+        let d = diff.get_or_insert(Difference {
+            count: 0,
+            first: ((i % width) as u32, (i / width) as u32),
+            max_delta: 0,
+        });
+        d.count += 1;
+        d.max_delta = d.max_delta.max(delta);
+    }
+    diff
+}
+
+/// Decodes the golden png at `expected_path` and compares `actual` with it.
+/// On a mismatch, writes the actual image to `<name>.actual.pam` and, if the
+/// sizes match, a diff to `<name>.diff.pam` next to the golden file, marking
+/// differing pixels red. Returns a message describing the failure.
+///
+/// The outputs are netpbm PAM files rather than pngs, as this crate can't
+/// encode pngs yet.
+pub fn check_snapshot(
+    actual: &Png,
+    expected_path: impl AsRef<Path>,
+    tolerance: u16,
+) -> Result<(), String> {
+    let path = expected_path.as_ref();
+    let expected = fs::File::open(path)
+        .and_then(|file| PngParser::new(io::BufReader::new(file))?.parse())
+        .map_err(|e| format!("Failed to decode golden file {}: {e}", path.display()))?;
+
+    let message = if (actual.width(), actual.height()) != (expected.width(), expected.height()) {
+        format!(
+            "Size mismatch against {}: actual {}x{}, expected {}x{}",
+            path.display(),
+            actual.width(),
+            actual.height(),
+            expected.width(),
+            expected.height()
+        )
+    } else if let Some(diff) = difference(actual, &expected, tolerance) {
+        let diff_path = output_path(path, "diff");
+        write_pam(&diff_image(actual, &expected, tolerance), &diff_path)
+            .map_err(|e| format!("Failed to write {}: {e}", diff_path.display()))?;
+        format!(
+            "{} pixels differ from {} by more than {tolerance}, first at {:?}, by up to {}. \
+             Diff written to {}",
+            diff.count,
+            path.display(),
+            diff.first,
+            diff.max_delta,
+            diff_path.display()
+        )
+    } else {
+        return Ok(());
+    };
+
+    let actual_path = output_path(path, "actual");
+    write_pam(actual, &actual_path)
+        .map_err(|e| format!("Failed to write {}: {e}", actual_path.display()))?;
+    Err(format!(
+        "{message}. Actual image written to {}",
+        actual_path.display()
+    ))
+}
+
+fn max_delta(a: Color, b: Color) -> u16 {
+    [
+        a.red().abs_diff(b.red()),
+        a.green().abs_diff(b.green()),
+        a.blue().abs_diff(b.blue()),
+        a.alpha().abs_diff(b.alpha()),
+    ]
+    .into_iter()
+    .max()
+    .expect("4 channels")
+}
+
+/// Red where the images differ, a faded copy of the expected image elsewhere
+fn diff_image(actual: &Png, expected: &Png, tolerance: u16) -> Png {
+    let pixels = actual
+        .pixels()
+        .zip(expected.pixels())
+        .map(|(&a, &e)| {
+            if max_delta(a, e) > tolerance {
+                Color::new_opaque(u16::MAX, 0, 0)
+            } else {
+                let faded = u16::MAX - (u16::MAX - e.luma()) / 4;
+                Color::new_opaque(faded, faded, faded)
+            }
+        })
+        .collect();
+    Png::new(actual.height(), actual.width(), pixels)
+}
+
+/// `golden.png` becomes `golden.<suffix>.pam`
+fn output_path(path: &Path, suffix: &str) -> PathBuf {
+    path.with_extension(format!("{suffix}.pam"))
+}
+
+/// Writes a 16 bit RGBA netpbm PAM file
+fn write_pam(image: &Png, path: &Path) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    write!(
+        out,
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 65535\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        image.width(),
+        image.height()
+    )?;
+    for c in image.pixels() {
+        for channel in [c.red(), c.green(), c.blue(), c.alpha()] {
+            out.write_all(&channel.to_be_bytes())?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TINY_PNG;
+
+    /// Writes TINY_PNG to a fresh directory, returning the file's path
+    fn golden(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("png-snapshot-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tiny.png");
+        fs::write(&path, TINY_PNG).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_matching_snapshot() {
+        let path = golden("matching");
+        let black = Png::new(1, 1, vec![Color::new_opaque(0, 0, 0)]);
+        assert_png_eq!(black, &path);
+
+        let near_black = Png::new(1, 1, vec![Color::new_opaque(0x0100, 0, 0)]);
+        assert_png_eq!(near_black, &path, 0x0100);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_mismatched_snapshot() {
+        let path = golden("mismatched");
+        let white = Png::new(1, 1, vec![Color::new_opaque(u16::MAX, u16::MAX, u16::MAX)]);
+        let message = check_snapshot(&white, &path, 0).unwrap_err();
+        assert!(message.starts_with("1 pixels differ"), "{message}");
+
+        let actual = fs::read(path.with_extension("actual.pam")).unwrap();
+        assert!(actual.starts_with(b"P7\nWIDTH 1\nHEIGHT 1\n"));
+        assert!(actual.ends_with(&[0xff; 8]));
+        let diff = fs::read(path.with_extension("diff.pam")).unwrap();
+        assert!(diff.ends_with(&[0xff, 0xff, 0, 0, 0, 0, 0xff, 0xff]));
+
+        let wide = Png::new(1, 2, vec![Color::new_opaque(0, 0, 0); 2]);
+        assert!(check_snapshot(&wide, &path, 0)
+            .unwrap_err()
+            .starts_with("Size mismatch"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to decode golden file")]
+    fn test_missing_golden() {
+        let image = Png::new(1, 1, vec![Color::new_opaque(0, 0, 0)]);
+        assert_png_eq!(image, "does/not/exist.png");
+    }
+}